@@ -0,0 +1,420 @@
+//! Sound banks: ordered, named collections of sounds.
+//!
+//! A bank is saved as JSON. Each entry stores its sound as a share code (see [`Asyn::to_code`]),
+//! so it loads back bit for bit, along with the preset and seed it came from, if any.
+
+use std::{error, fmt, fs, io, path::Path, path::PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::{code::CodeError, error::Error, presets, profile::ExportProfile, types::Asyn};
+
+/// Identifies a bank document.
+const BANK_FORMAT: &str = "asyn-bank";
+
+/// The current bank format version.
+pub const BANK_VERSION: u64 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BankEntry {
+    /// Unique within a bank. Also the file name when rendered.
+    pub name: String,
+    pub tags: Vec<String>,
+    pub notes: String,
+    /// The preset the sound was generated from.
+    pub preset: Option<String>,
+    /// The seed fed to the preset.
+    pub seed: Option<u64>,
+    pub asyn: Asyn,
+}
+
+impl BankEntry {
+    pub fn new(name: impl Into<String>, asyn: Asyn) -> Self {
+        Self {
+            name: name.into(),
+            tags: Vec::new(),
+            notes: String::new(),
+            preset: None,
+            seed: None,
+            asyn,
+        }
+    }
+
+    /// Generate the sound with the named preset, seeded with `seed`.
+    pub fn from_preset(
+        name: impl Into<String>,
+        preset: &str,
+        seed: u64,
+    ) -> Result<Self, BankError> {
+        let f = presets::preset(preset).ok_or_else(|| BankError::UnknownPreset(preset.into()))?;
+        Ok(Self {
+            preset: Some(preset.into()),
+            seed: Some(seed),
+            ..Self::new(name, f(&mut funutd::Rnd::from_u64(seed)))
+        })
+    }
+
+    pub fn with_tags<S: Into<String>>(mut self, tags: impl IntoIterator<Item = S>) -> Self {
+        self.tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_notes(mut self, notes: impl Into<String>) -> Self {
+        self.notes = notes.into();
+        self
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "tags": self.tags,
+            "notes": self.notes,
+            "preset": self.preset,
+            "seed": self.seed,
+            "code": self.asyn.to_code(),
+        })
+    }
+
+    fn from_json(value: &Value) -> Result<Self, BankError> {
+        let field = |key: &'static str| value.get(key).filter(|v| !v.is_null());
+        let string = |key: &'static str| -> Result<Option<String>, BankError> {
+            field(key)
+                .map(|v| v.as_str().map(String::from).ok_or(BankError::Format(key)))
+                .transpose()
+        };
+
+        let name = string("name")?.ok_or(BankError::Format("name"))?;
+        let code = string("code")?.ok_or(BankError::Format("code"))?;
+        let asyn = Asyn::from_code(&code).map_err(|error| BankError::Code {
+            name: name.clone(),
+            error,
+        })?;
+
+        let tags = match field("tags") {
+            None => Vec::new(),
+            Some(v) => v
+                .as_array()
+                .and_then(|tags| {
+                    tags.iter()
+                        .map(|t| t.as_str().map(String::from))
+                        .collect::<Option<_>>()
+                })
+                .ok_or(BankError::Format("tags"))?,
+        };
+
+        Ok(Self {
+            name,
+            tags,
+            notes: string("notes")?.unwrap_or_default(),
+            preset: string("preset")?,
+            seed: field("seed")
+                .map(|v| v.as_u64().ok_or(BankError::Format("seed")))
+                .transpose()?,
+            asyn,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum BankError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// Not a bank document, or the named field is missing or has the wrong type.
+    Format(&'static str),
+    Version(u64),
+    /// An entry's share code failed to decode.
+    Code {
+        name: String,
+        error: CodeError,
+    },
+    DuplicateName(String),
+    /// A name that can't be used as a file name.
+    InvalidName(String),
+    UnknownPreset(String),
+    /// Rendering or writing an entry failed.
+    Export(Error),
+}
+
+impl fmt::Display for BankError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "bank i/o error: {e}"),
+            Self::Json(e) => write!(f, "invalid bank json: {e}"),
+            Self::Format(key) => write!(f, "invalid bank: bad or missing {key}"),
+            Self::Version(v) => write!(f, "unsupported bank version: {v}"),
+            Self::Code { name, error } => write!(f, "invalid sound {name:?}: {error}"),
+            Self::DuplicateName(name) => write!(f, "duplicate sound name: {name:?}"),
+            Self::InvalidName(name) => write!(f, "invalid sound name: {name:?}"),
+            Self::UnknownPreset(name) => write!(f, "unknown preset: {name:?}"),
+            Self::Export(e) => write!(f, "bank export failed: {e}"),
+        }
+    }
+}
+
+impl error::Error for BankError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Code { error, .. } => Some(error),
+            Self::Export(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BankError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<Error> for BankError {
+    fn from(e: Error) -> Self {
+        Self::Export(e)
+    }
+}
+
+impl From<serde_json::Error> for BankError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Whether `name` is a file name on every platform we render on.
+fn valid_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default();
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(|c: char| {
+            matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') || c.is_control()
+        })
+        && !RESERVED_NAMES
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(stem.trim_end()))
+}
+
+/// An ordered collection of named sounds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SoundBank {
+    entries: Vec<BankEntry>,
+}
+
+impl SoundBank {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an entry to the end of the bank. Names must be unique, non-empty and usable as file
+    /// names.
+    pub fn push(&mut self, entry: BankEntry) -> Result<(), BankError> {
+        let name = &entry.name;
+        if !valid_name(name) {
+            return Err(BankError::InvalidName(name.clone()));
+        }
+        if self.get(name).is_some() {
+            return Err(BankError::DuplicateName(name.clone()));
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Remove an entry by name.
+    pub fn remove(&mut self, name: &str) -> Option<BankEntry> {
+        let i = self.entries.iter().position(|e| e.name == name)?;
+        Some(self.entries.remove(i))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BankEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Entries with the given tag, in bank order.
+    pub fn tagged<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a BankEntry> + 'a {
+        self.entries.iter().filter(move |e| e.has_tag(tag))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BankEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        let json = json!({
+            "format": BANK_FORMAT,
+            "version": BANK_VERSION,
+            "sounds": self.entries.iter().map(BankEntry::to_json).collect::<Vec<_>>(),
+        });
+        // Pretty, so banks diff well under version control.
+        serde_json::to_string_pretty(&json).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, BankError> {
+        let value: Value = serde_json::from_str(json)?;
+
+        if value.get("format").and_then(Value::as_str) != Some(BANK_FORMAT) {
+            return Err(BankError::Format("format"));
+        }
+        match value.get("version").and_then(Value::as_u64) {
+            Some(BANK_VERSION) => (),
+            Some(v) => return Err(BankError::Version(v)),
+            None => return Err(BankError::Format("version")),
+        }
+
+        let mut bank = Self::new();
+        for entry in value
+            .get("sounds")
+            .and_then(Value::as_array)
+            .ok_or(BankError::Format("sounds"))?
+        {
+            bank.push(BankEntry::from_json(entry)?)?;
+        }
+        Ok(bank)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BankError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BankError> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+
+    /// Render every entry with `profile` to `<dir>/<name>.<ext>`, where the extension is the
+    /// profile's container's, creating `dir` if needed. Returns the paths written, in bank order.
+    pub fn render_dir(
+        &self,
+        dir: impl AsRef<Path>,
+        profile: &ExportProfile,
+    ) -> Result<Vec<PathBuf>, BankError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        self.entries
+            .iter()
+            .map(|entry| {
+                let path = dir.join(format!("{}.{}", entry.name, profile.container.extension()));
+                profile.save(&entry.asyn, &path)?;
+                Ok(path)
+            })
+            .collect()
+    }
+}
+
+impl<'a> IntoIterator for &'a SoundBank {
+    type Item = &'a BankEntry;
+    type IntoIter = std::slice::Iter<'a, BankEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bank() -> SoundBank {
+        let mut bank = SoundBank::new();
+        for (i, (preset, _)) in presets::PRESETS.iter().enumerate() {
+            let entry = BankEntry::from_preset(format!("{preset}-{i}"), preset, i as u64)
+                .unwrap()
+                .with_tags(["ui", preset])
+                .with_notes("generated");
+            bank.push(entry).unwrap();
+        }
+        let mutated =
+            presets::jump(&mut funutd::Rnd::from_u64(9)).mutate(&mut funutd::Rnd::from_u64(10));
+        bank.push(BankEntry::new("mutated jump", mutated).with_tags(["player"]))
+            .unwrap();
+        bank
+    }
+
+    #[test]
+    fn round_trip() {
+        let bank = bank();
+        let loaded = SoundBank::from_json(&bank.to_json()).unwrap();
+        assert_eq!(loaded, bank);
+
+        assert_eq!(loaded.get("laser-4").unwrap().seed, Some(4));
+        assert_eq!(
+            loaded.get("laser-4").unwrap().asyn,
+            presets::laser(&mut funutd::Rnd::from_u64(4))
+        );
+        assert_eq!(loaded.tagged("ui").count(), 8);
+        assert_eq!(
+            loaded.tagged("player").map(|e| &e.name).collect::<Vec<_>>(),
+            ["mutated jump"]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut bank = bank();
+        assert!(matches!(
+            bank.push(BankEntry::new("blip-0", Asyn::default())),
+            Err(BankError::DuplicateName(_))
+        ));
+        for name in ["../blip", "a:b", "what?", "nul", "Com1.blip"] {
+            assert!(matches!(
+                bank.push(BankEntry::new(name, Asyn::default())),
+                Err(BankError::InvalidName(_))
+            ));
+        }
+        bank.push(BankEntry::new("console", Asyn::default()))
+            .unwrap();
+        assert!(matches!(
+            BankEntry::from_preset("x", "kazoo", 0),
+            Err(BankError::UnknownPreset(_))
+        ));
+
+        let json = bank.to_json().replace(r#""version": 1"#, r#""version": 2"#);
+        assert!(matches!(
+            SoundBank::from_json(&json),
+            Err(BankError::Version(2))
+        ));
+        assert!(matches!(
+            SoundBank::from_json("{}"),
+            Err(BankError::Format("format"))
+        ));
+    }
+
+    #[test]
+    fn render_dir() {
+        let dir = std::env::temp_dir().join(format!("asyn-bank-{}", std::process::id()));
+        let mut bank = SoundBank::new();
+        bank.push(BankEntry::from_preset("coin", "pickup", 1).unwrap())
+            .unwrap();
+        bank.push(BankEntry::from_preset("zap", "laser", 2).unwrap())
+            .unwrap();
+
+        let paths = bank
+            .render_dir(&dir, &crate::profile("modern-44k").unwrap())
+            .unwrap();
+        assert_eq!(paths, [dir.join("coin.wav"), dir.join("zap.wav")]);
+        assert!(paths.iter().all(|p| p.exists()));
+
+        let paths = bank
+            .render_dir(&dir, &crate::profile("gba-16k").unwrap())
+            .unwrap();
+        assert_eq!(paths, [dir.join("coin.pcm"), dir.join("zap.pcm")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,259 @@
+//! Rendering many sounds at once, across threads.
+//!
+//! Each item renders on its own, so the results don't depend on the number of threads or the
+//! order the items finish in. Results come back in item order.
+
+use std::{
+    error, fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use fundsp::hacker32::Wave32;
+
+use crate::{
+    error::Error,
+    presets,
+    types::{Asyn, RenderOptions},
+};
+
+/// A sound to render.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchItem {
+    Asyn(Asyn),
+    /// Generated with the named preset, seeded with `seed` (as [`crate::BankEntry::from_preset`]).
+    Preset {
+        name: String,
+        seed: u64,
+    },
+}
+
+impl BatchItem {
+    pub fn preset(name: impl Into<String>, seed: u64) -> Self {
+        Self::Preset {
+            name: name.into(),
+            seed,
+        }
+    }
+
+    pub fn to_asyn(&self) -> Result<Asyn, BatchError> {
+        match self {
+            Self::Asyn(asyn) => Ok(asyn.clone()),
+            Self::Preset { name, seed } => presets::preset(name)
+                .map(|f| f(&mut funutd::Rnd::from_u64(*seed)))
+                .ok_or_else(|| BatchError::UnknownPreset(name.clone())),
+        }
+    }
+}
+
+impl From<Asyn> for BatchItem {
+    fn from(asyn: Asyn) -> Self {
+        Self::Asyn(asyn)
+    }
+}
+
+#[derive(Debug)]
+pub enum BatchError {
+    UnknownPreset(String),
+    /// Rendering panicked, with the panic message.
+    Panic(String),
+    /// Processing the render failed.
+    Output(Error),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownPreset(name) => write!(f, "unknown preset: {name:?}"),
+            Self::Panic(message) => write!(f, "render panicked: {message}"),
+            Self::Output(e) => write!(f, "output failed: {e}"),
+        }
+    }
+}
+
+impl error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Output(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for BatchError {
+    fn from(e: Error) -> Self {
+        Self::Output(e)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatchOptions {
+    pub render: RenderOptions,
+    /// Worker threads. 0 uses one per core.
+    pub threads: usize,
+}
+
+/// Reported as each item finishes, from the thread that rendered it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The item that finished.
+    pub index: usize,
+    pub ok: bool,
+    /// Items finished so far, including this one.
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Render every item. See [`render_batch_with`].
+pub fn render_batch(
+    items: &[BatchItem],
+    options: &BatchOptions,
+    progress: impl Fn(Progress) + Sync,
+) -> Vec<Result<Wave32, BatchError>> {
+    render_batch_with(items, options, |_, wave| Ok(wave), progress)
+}
+
+/// Render every item and pass each render to `output` with its index, e.g. to save or encode it
+/// without keeping all the renders in memory. Returns the results in item order.
+pub fn render_batch_with<T: Send>(
+    items: &[BatchItem],
+    options: &BatchOptions,
+    output: impl Fn(usize, Wave32) -> Result<T, Error> + Sync,
+    progress: impl Fn(Progress) + Sync,
+) -> Vec<Result<T, BatchError>> {
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
+    .min(items.len());
+
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<T, BatchError>>>> =
+        Mutex::new(items.iter().map(|_| None).collect());
+
+    let work = || loop {
+        let index = next.fetch_add(1, Ordering::Relaxed);
+        let Some(item) = items.get(index) else {
+            break;
+        };
+
+        let result = item.to_asyn().and_then(|asyn| {
+            let wave = panic::catch_unwind(AssertUnwindSafe(|| asyn.render(&options.render)))
+                .map_err(|payload| {
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    BatchError::Panic(message)
+                })?;
+            Ok(output(index, wave)?)
+        });
+
+        let ok = result.is_ok();
+        results.lock().unwrap()[index] = Some(result);
+        progress(Progress {
+            index,
+            ok,
+            done: done.fetch_add(1, Ordering::Relaxed) + 1,
+            total: items.len(),
+        });
+    };
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(work);
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(Option::unwrap)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<BatchItem> {
+        let mut items: Vec<BatchItem> = presets::PRESETS
+            .iter()
+            .enumerate()
+            .map(|(seed, (name, _))| BatchItem::preset(*name, seed as u64))
+            .collect();
+        items.push(presets::laser(&mut funutd::Rnd::from_u64(3)).into());
+        items.insert(2, BatchItem::preset("kazoo", 0));
+        items
+    }
+
+    #[test]
+    fn deterministic() {
+        let items = items();
+        let render = |threads| {
+            let options = BatchOptions {
+                threads,
+                ..Default::default()
+            };
+            let seen = Mutex::new(Vec::new());
+            let results = render_batch(&items, &options, |p| seen.lock().unwrap().push(p));
+
+            let mut seen = seen.into_inner().unwrap();
+            assert_eq!(seen.len(), items.len());
+            seen.sort_by_key(|p| p.done);
+            assert!(seen.iter().enumerate().all(|(i, p)| p.done == i + 1));
+            assert!(seen.iter().all(|p| p.ok == (p.index != 2)));
+            results
+        };
+
+        let one = render(1);
+        let many = render(4);
+        for (i, (a, b)) in one.iter().zip(&many).enumerate() {
+            match (a, b) {
+                (Ok(a), Ok(b)) => {
+                    let expected = items[i].to_asyn().unwrap().to_wav();
+                    for wave in [a, b] {
+                        assert_eq!(wave.len(), expected.len());
+                        assert!((0..wave.len())
+                            .all(|j| wave.at(0, j).to_bits() == expected.at(0, j).to_bits()));
+                    }
+                }
+                (Err(BatchError::UnknownPreset(a)), Err(BatchError::UnknownPreset(b))) => {
+                    assert_eq!((i, a, b), (2, &"kazoo".to_string(), &"kazoo".to_string()));
+                }
+                _ => panic!("item {i} differs"),
+            }
+        }
+    }
+
+    #[test]
+    fn output_errors() {
+        let items = items();
+        let results = render_batch_with(
+            &items,
+            &BatchOptions::default(),
+            |index, wave| match index {
+                5 => Err(Error::Format("rejected")),
+                _ => Ok(wave.len()),
+            },
+            |_| (),
+        );
+        assert_eq!(results.len(), items.len());
+        assert!(matches!(
+            results[5],
+            Err(BatchError::Output(Error::Format("rejected")))
+        ));
+        assert!(matches!(results[2], Err(BatchError::UnknownPreset(_))));
+        assert_eq!(
+            results.iter().filter(|r| r.is_ok()).count(),
+            items.len() - 2
+        );
+    }
+}
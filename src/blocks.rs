@@ -0,0 +1,278 @@
+//! Streaming renders.
+//!
+//! [`Asyn::render_blocks`] produces a sound a block at a time instead of all at once. The net is
+//! run in the same [`MAX_BUFFER_SIZE`] steps as [`Wave32::render`], so the samples match
+//! [`Asyn::render`] bit for bit. Normalization needs the peak of the whole sound, so it has to be
+//! passed in. [`Asyn::peak`] finds it with a full render of its own, so work it out once ahead of
+//! time, not before every stream. Render options that need the whole sound (oversampling,
+//! loudness and trimming) are rejected.
+
+use fundsp::{
+    hacker32::{AudioUnit32, Net32, Wave32},
+    MAX_BUFFER_SIZE,
+};
+
+use crate::{
+    error::Error,
+    types::{Asyn, Gain, RenderOptions},
+};
+
+/// Runs a net in the same steps as [`Wave32::render`].
+struct Stepper {
+    net: Net32,
+    /// Samples left to render.
+    remaining: usize,
+    buffer: Vec<Vec<f32>>,
+}
+
+impl Stepper {
+    fn new(asyn: Asyn, sample_rate: f64) -> Self {
+        let length = (asyn.len() as f64 * sample_rate).round() as usize;
+        let mut net = asyn.to_net_at(sample_rate);
+        net.reset(Some(sample_rate));
+        Self {
+            buffer: vec![vec![0.0; MAX_BUFFER_SIZE]; net.outputs()],
+            net,
+            remaining: length,
+        }
+    }
+
+    /// The next step of samples per channel, or `None` at the end.
+    fn step(&mut self) -> Option<&[Vec<f32>]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let n = self.remaining.min(MAX_BUFFER_SIZE);
+        for channel in &mut self.buffer {
+            channel.resize(n, 0.0);
+        }
+        let mut output: Vec<&mut [f32]> = self.buffer.iter_mut().map(|c| &mut c[..]).collect();
+        self.net.process(n, &[], &mut output);
+        self.remaining -= n;
+        Some(&self.buffer)
+    }
+}
+
+/// An iterator of rendered blocks. See [`Asyn::render_blocks`].
+pub struct RenderBlocks {
+    stepper: Stepper,
+    gain: Option<Gain>,
+    sample_rate: f64,
+    block_size: usize,
+    /// Rendered samples not yet returned, per channel.
+    pending: Vec<Vec<f32>>,
+}
+
+impl RenderBlocks {
+    pub fn channels(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+}
+
+impl Iterator for RenderBlocks {
+    type Item = Wave32;
+
+    fn next(&mut self) -> Option<Wave32> {
+        while self.pending[0].len() < self.block_size {
+            let Some(step) = self.stepper.step() else {
+                break;
+            };
+            for (pending, samples) in self.pending.iter_mut().zip(step) {
+                pending.extend(samples.iter().map(|x| match self.gain {
+                    Some(gain) => gain.apply(*x),
+                    None => *x,
+                }));
+            }
+        }
+
+        let n = self.pending[0].len().min(self.block_size);
+        if n == 0 {
+            return None;
+        }
+        let mut block = Wave32::new(0, self.sample_rate);
+        for pending in &mut self.pending {
+            block.push_channel(&pending[..n]);
+            pending.drain(..n);
+        }
+        Some(block)
+    }
+}
+
+impl Asyn {
+    /// The peak of the render at `sample_rate` before normalization and amplification, as
+    /// [`Asyn::render_blocks`] needs for normalization. The render isn't kept.
+    pub fn peak(&self, sample_rate: f64) -> f32 {
+        let mut stepper = Stepper::new(self.clone(), sample_rate);
+        let mut peak = 0.0f32;
+        while let Some(step) = stepper.step() {
+            peak = step.iter().flatten().fold(peak, |p, x| p.max(x.abs()));
+        }
+        peak
+    }
+
+    /// Render in blocks of `block_size` samples per channel. The last block holds what is left,
+    /// through the end of the tail. Joined, the blocks are exactly [`Asyn::render`] with the same
+    /// options.
+    ///
+    /// `peak` is [`Asyn::peak`] at the same sample rate, and is needed if the filters normalize.
+    /// That is a full render before the first block, so keep it rather than finding it each time.
+    /// Oversampling, loudness and trimming need the whole render, so they are errors.
+    pub fn render_blocks(
+        &self,
+        options: &RenderOptions,
+        block_size: usize,
+        peak: Option<f32>,
+    ) -> Result<RenderBlocks, Error> {
+        if block_size == 0 {
+            return Err(Error::Invalid {
+                parameter: "block size",
+                reason: "must be positive",
+            });
+        }
+
+        let whole = |parameter| Error::Invalid {
+            parameter,
+            reason: "needs the whole render, so can't be streamed",
+        };
+        if options.oversample > 1 {
+            return Err(whole("oversample"));
+        }
+        if options.loudness.is_some() {
+            return Err(whole("loudness"));
+        }
+        if options.trim.is_some() {
+            return Err(whole("trim"));
+        }
+
+        let gain = match &self.filters {
+            Some(filters) if filters.normalization => match peak {
+                Some(peak) => filters.gain(peak),
+                None => {
+                    return Err(Error::Invalid {
+                        parameter: "peak",
+                        reason: "normalization needs the peak of the whole render",
+                    })
+                }
+            },
+            // Only normalization uses the peak.
+            Some(filters) => filters.gain(0.0),
+            None => None,
+        };
+
+        let sample_rate = options.sample_rate;
+        let stepper = Stepper::new(self.clone(), sample_rate);
+        Ok(RenderBlocks {
+            pending: vec![Vec::with_capacity(block_size + MAX_BUFFER_SIZE); stepper.buffer.len()],
+            stepper,
+            gain,
+            sample_rate,
+            block_size,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{presets::*, Stereo};
+
+    fn options(sample_rate: f64) -> RenderOptions {
+        RenderOptions {
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_as_full_render() {
+        let rng = &mut funutd::Rnd::from_u64(16);
+        let sounds = [
+            explosion(rng),
+            laser(rng),
+            Asyn {
+                stereo: Some(Stereo {
+                    pan_sweep: 0.5,
+                    width: 0.5,
+                    ..Default::default()
+                }),
+                ..pickup(rng)
+            },
+            // No filters.
+            Asyn {
+                filters: None,
+                ..blip(rng)
+            },
+        ];
+
+        for asyn in sounds {
+            for (sample_rate, block_size) in [(44_100.0, 1), (44_100.0, 1000), (22_050.0, 4096)] {
+                let wave = asyn.clone().render(&options(sample_rate));
+                let peak = Some(asyn.peak(sample_rate));
+                let blocks: Vec<Wave32> = asyn
+                    .render_blocks(&options(sample_rate), block_size, peak)
+                    .unwrap()
+                    .collect();
+
+                assert!(blocks[..blocks.len() - 1]
+                    .iter()
+                    .all(|b| b.len() == block_size));
+                assert_eq!(blocks.iter().map(Wave32::len).sum::<usize>(), wave.len());
+                for channel in 0..wave.channels() {
+                    let joined = blocks.iter().flat_map(|b| b.channel(channel).iter());
+                    assert!(joined
+                        .zip(wave.channel(channel))
+                        .all(|(a, b)| a.to_bits() == b.to_bits()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let asyn = explosion(&mut funutd::Rnd::from_u64(16));
+        assert!(asyn.filters.as_ref().unwrap().normalization);
+        let error = |options: &RenderOptions, peak| match asyn.render_blocks(options, 64, peak) {
+            Err(Error::Invalid { parameter, .. }) => parameter,
+            _ => "",
+        };
+
+        assert_eq!(error(&RenderOptions::default(), None), "peak");
+        let peak = Some(asyn.peak(44_100.0));
+        assert!(matches!(
+            asyn.render_blocks(&RenderOptions::default(), 0, peak),
+            Err(Error::Invalid {
+                parameter: "block size",
+                ..
+            })
+        ));
+        for (options, parameter) in [
+            (
+                RenderOptions {
+                    oversample: 2,
+                    ..Default::default()
+                },
+                "oversample",
+            ),
+            (
+                RenderOptions {
+                    loudness: Some(-16.0),
+                    ..Default::default()
+                },
+                "loudness",
+            ),
+            (
+                RenderOptions {
+                    trim: Some(0.001),
+                    ..Default::default()
+                },
+                "trim",
+            ),
+        ] {
+            assert_eq!(error(&options, peak), parameter);
+        }
+    }
+}
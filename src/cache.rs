@@ -0,0 +1,346 @@
+//! A render cache, keyed by content.
+//!
+//! The key is the sound's binary share code (every field that changes the render, bit for bit)
+//! plus the render options.
+//! Renders are kept in memory up to a byte limit, least recently used first out, and optionally
+//! in a directory so they survive across runs. Disk entries store their full key, so a hash
+//! collision is a miss rather than the wrong sound.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use fundsp::hacker32::Wave32;
+
+use crate::{
+    error::Error,
+    types::{Asyn, RenderOptions},
+};
+
+/// Bump when rendering changes, so old disk entries aren't reused.
+pub const CACHE_VERSION: u8 = 2;
+
+const MAGIC: &[u8; 8] = b"asyncach";
+
+/// The cache key of a render.
+pub fn cache_key(asyn: &Asyn, options: &RenderOptions) -> Vec<u8> {
+    let mut key = vec![CACHE_VERSION];
+    key.extend(options.sample_rate.to_le_bytes());
+    match options.trim {
+        Some(threshold) => {
+            key.push(1);
+            key.extend(threshold.to_le_bytes());
+        }
+        None => key.push(0),
+    }
+    key.extend((options.oversample.max(1) as u64).to_le_bytes());
+    match options.loudness {
+        Some(target) => {
+            key.push(1);
+            key.extend(target.to_le_bytes());
+        }
+        None => key.push(0),
+    }
+    // The mutation count is only bookkeeping.
+    let asyn = Asyn {
+        mutations: 0,
+        ..asyn.clone()
+    };
+    key.extend(asyn.to_bytes());
+    key
+}
+
+/// 64-bit FNV-1a, which is stable across runs and platforms.
+fn hash(key: &[u8]) -> u64 {
+    key.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn wave_bytes(wave: &Wave32) -> usize {
+    wave.channels() * wave.len() * 4
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub memory_hits: usize,
+    pub disk_hits: usize,
+    pub misses: usize,
+}
+
+struct Entry {
+    key: Vec<u8>,
+    wave: Arc<Wave32>,
+    last_used: u64,
+}
+
+pub struct RenderCache {
+    /// Bytes of samples to keep in memory.
+    memory_limit: usize,
+    memory_used: usize,
+    entries: HashMap<u64, Entry>,
+    /// Use counter for the LRU.
+    clock: u64,
+    dir: Option<PathBuf>,
+    stats: CacheStats,
+}
+
+impl RenderCache {
+    /// A cache holding up to `memory_limit` bytes of samples in memory.
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            memory_limit,
+            memory_used: 0,
+            entries: HashMap::new(),
+            clock: 0,
+            dir: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Also keep renders in `dir`, creating it if needed.
+    pub fn with_dir(mut self, dir: impl AsRef<Path>) -> Result<Self, Error> {
+        fs::create_dir_all(&dir)?;
+        self.dir = Some(dir.as_ref().into());
+        Ok(self)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Bytes of samples in memory.
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    /// Empty the memory cache. The directory is left alone.
+    pub fn clear_memory(&mut self) {
+        self.entries.clear();
+        self.memory_used = 0;
+    }
+
+    /// [`Asyn::to_wav`], cached.
+    pub fn to_wav(&mut self, asyn: &Asyn) -> Result<Arc<Wave32>, Error> {
+        self.render(asyn, &RenderOptions::default())
+    }
+
+    /// [`Asyn::render`], cached. Errors are from writing the directory; unreadable entries there
+    /// are rendered again.
+    pub fn render(&mut self, asyn: &Asyn, options: &RenderOptions) -> Result<Arc<Wave32>, Error> {
+        let key = cache_key(asyn, options);
+        let hash = hash(&key);
+        self.clock += 1;
+
+        if let Some(entry) = self.entries.get_mut(&hash).filter(|e| e.key == key) {
+            entry.last_used = self.clock;
+            self.stats.memory_hits += 1;
+            return Ok(entry.wave.clone());
+        }
+
+        let path = self
+            .dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{hash:016x}.bin")));
+        let cached = path.as_ref().and_then(|path| read_entry(path, &key).ok());
+        let wave = match cached {
+            Some(wave) => {
+                self.stats.disk_hits += 1;
+                Arc::new(wave)
+            }
+            None => {
+                self.stats.misses += 1;
+                let wave = asyn.clone().render(options);
+                if let Some(path) = &path {
+                    write_entry(path, &key, &wave)?;
+                }
+                Arc::new(wave)
+            }
+        };
+
+        self.insert(hash, key, wave.clone());
+        Ok(wave)
+    }
+
+    fn insert(&mut self, hash: u64, key: Vec<u8>, wave: Arc<Wave32>) {
+        let size = wave_bytes(&wave);
+        if size > self.memory_limit {
+            return;
+        }
+        if let Some(old) = self.entries.remove(&hash) {
+            self.memory_used -= wave_bytes(&old.wave);
+        }
+        while self.memory_used + size > self.memory_limit {
+            let lru = *self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(hash, _)| hash)
+                .unwrap();
+            let old = self.entries.remove(&lru).unwrap();
+            self.memory_used -= wave_bytes(&old.wave);
+        }
+        self.memory_used += size;
+        self.entries.insert(
+            hash,
+            Entry {
+                key,
+                wave,
+                last_used: self.clock,
+            },
+        );
+    }
+}
+
+/// Magic, key length and key, channels, sample rate, length, then each channel's samples. All
+/// little-endian.
+fn write_entry(path: &Path, key: &[u8], wave: &Wave32) -> Result<(), Error> {
+    let mut bytes = Vec::with_capacity(40 + key.len() + wave_bytes(wave));
+    bytes.extend(MAGIC);
+    bytes.extend((key.len() as u32).to_le_bytes());
+    bytes.extend(key);
+    bytes.extend((wave.channels() as u32).to_le_bytes());
+    bytes.extend(wave.sample_rate().to_le_bytes());
+    bytes.extend((wave.len() as u64).to_le_bytes());
+    for channel in 0..wave.channels() {
+        for x in wave.channel(channel) {
+            bytes.extend(x.to_le_bytes());
+        }
+    }
+
+    // Write then rename, so readers never see a partial entry.
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    let mut file = fs::File::create(&temp)?;
+    file.write_all(&bytes)?;
+    drop(file);
+    Ok(fs::rename(temp, path)?)
+}
+
+fn read_entry(path: &Path, key: &[u8]) -> io::Result<Wave32> {
+    let bytes = fs::read(path)?;
+    let invalid = || io::Error::from(io::ErrorKind::InvalidData);
+    let mut rest = &bytes[..];
+    let mut take = |n: usize| -> io::Result<&[u8]> {
+        let (head, tail) = rest.split_at_checked(n).ok_or_else(invalid)?;
+        rest = tail;
+        Ok(head)
+    };
+
+    if take(8)? != MAGIC {
+        return Err(invalid());
+    }
+    let key_len = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+    if take(key_len)? != key {
+        return Err(invalid());
+    }
+    let channels = u32::from_le_bytes(take(4)?.try_into().unwrap()) as usize;
+    let sample_rate = f64::from_le_bytes(take(8)?.try_into().unwrap());
+    let len = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+    if channels == 0 || rest.len() != channels * len * 4 {
+        return Err(invalid());
+    }
+
+    let mut wave = Wave32::new(0, sample_rate);
+    for samples in rest.chunks_exact(len * 4).take(channels) {
+        let samples: Vec<f32> = samples
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        wave.push_channel(&samples);
+    }
+    // Zero-length waves have no chunks.
+    while wave.channels() < channels {
+        wave.push_channel(&[]);
+    }
+    Ok(wave)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::*;
+
+    fn same(a: &Wave32, b: &Wave32) -> bool {
+        a.len() == b.len()
+            && a.channels() == b.channels()
+            && (0..a.channels())
+                .all(|c| (0..a.len()).all(|i| a.at(c, i).to_bits() == b.at(c, i).to_bits()))
+    }
+
+    #[test]
+    fn memory_and_disk() {
+        let dir = std::env::temp_dir().join(format!("asyn-cache-{}", std::process::id()));
+        let rng = &mut funutd::Rnd::from_u64(18);
+        let (a, b) = (laser(rng), pickup(rng));
+
+        let mut cache = RenderCache::new(usize::MAX).with_dir(&dir).unwrap();
+        let wave = cache.to_wav(&a).unwrap();
+        assert!(same(&wave, &a.clone().to_wav()));
+        assert!(Arc::ptr_eq(&wave, &cache.to_wav(&a).unwrap()));
+        let at_22k = RenderOptions {
+            sample_rate: 22_050.0,
+            ..Default::default()
+        };
+        assert_eq!(cache.render(&a, &at_22k).unwrap().sample_rate(), 22_050.0);
+        cache.to_wav(&b).unwrap();
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                memory_hits: 1,
+                disk_hits: 0,
+                misses: 3,
+            }
+        );
+
+        // A later run.
+        let mut cache = RenderCache::new(usize::MAX).with_dir(&dir).unwrap();
+        assert!(same(&cache.to_wav(&a).unwrap(), &wave));
+        assert_eq!(cache.stats().disk_hits, 1);
+
+        // A different seed is a different sound.
+        let reseeded = Asyn { seed: 1, ..a };
+        cache.to_wav(&reseeded).unwrap();
+        assert_eq!(cache.stats().misses, 1);
+
+        // The mutation count doesn't change the sound.
+        let recounted = Asyn {
+            mutations: 7,
+            ..reseeded
+        };
+        cache.to_wav(&recounted).unwrap();
+        assert_eq!(cache.stats().memory_hits, 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lru() {
+        let rng = &mut funutd::Rnd::from_u64(19);
+        // The seed only changes noise, so these are the same size.
+        let sound = hit(rng);
+        let sounds = [0, 1, 2].map(|seed| Asyn {
+            seed,
+            ..sound.clone()
+        });
+        let size = wave_bytes(&sound.to_wav());
+
+        // Room for the first two.
+        let mut cache = RenderCache::new(size * 2);
+        cache.to_wav(&sounds[0]).unwrap();
+        cache.to_wav(&sounds[1]).unwrap();
+        cache.to_wav(&sounds[0]).unwrap();
+        assert_eq!(cache.memory_used(), size * 2);
+
+        // The second is least recently used.
+        cache.to_wav(&sounds[2]).unwrap();
+        assert_eq!(cache.memory_used(), size * 2);
+        cache.to_wav(&sounds[0]).unwrap();
+        assert_eq!(cache.stats().memory_hits, 2);
+        cache.to_wav(&sounds[1]).unwrap();
+        assert_eq!(cache.stats().misses, 4);
+    }
+}
@@ -0,0 +1,521 @@
+//! Compact share codes.
+//!
+//! The binary form is a version byte, the seed and mutation count, a bit mask of the parameters
+//! that differ from their defaults, those parameters, and a CRC-32 of everything before it. Floats
+//! are stored bit for bit, so a decoded sound renders exactly the same. The text form is the
+//! binary form in unpadded base64url.
+
+use std::{error, fmt};
+
+use flagset::Flags;
+
+use crate::types::{Amplitude, Asyn, Filters, Pitch, Stereo, Tone, Waveform, MAX_HARMONICS};
+
+/// The current binary format version. Version 1 codes predate normalization and amplification,
+/// and decode with both turned off. Version 2 codes predate stereo, and decode as mono. Version 3
+/// and older codes have flanger offsets in seconds, and decode in milliseconds, so they render the
+/// same up to rounding. Version 4 codes predate band-limited oscillators. Version 5 codes have
+/// exactly two jumps, and decode without the ones that do nothing.
+pub const CODE_VERSION: u8 = 6;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodeError {
+    /// Not base64url.
+    Base64,
+    Checksum,
+    /// The code ends early.
+    Truncated,
+    Version(u8),
+    /// A parameter is out of range.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for CodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Base64 => write!(f, "invalid characters in code"),
+            Self::Checksum => write!(f, "code checksum mismatch"),
+            Self::Truncated => write!(f, "code is truncated"),
+            Self::Version(v) => write!(f, "unsupported code version: {v}"),
+            Self::Invalid(name) => write!(f, "invalid value for {name}"),
+        }
+    }
+}
+
+impl error::Error for CodeError {}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity((bytes.len() * 4).div_ceil(3));
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            s.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    s
+}
+
+fn base64_decode(s: &str) -> Result<Vec<u8>, CodeError> {
+    let mut bytes = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.as_bytes().chunks(4) {
+        if chunk.len() == 1 {
+            return Err(CodeError::Base64);
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = BASE64
+                .iter()
+                .position(|b| b == c)
+                .ok_or(CodeError::Base64)?;
+            n |= (v as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, b| {
+        (0..8).fold(crc ^ *b as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+fn put_varint(bytes: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        bytes.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    bytes.push(v as u8);
+}
+
+/// Writes the parameters that differ from their defaults, one mask bit each.
+#[derive(Default)]
+struct Encoder {
+    mask: u64,
+    bit: u32,
+    body: Vec<u8>,
+}
+
+impl Encoder {
+    fn next(&mut self, differs: bool) -> bool {
+        if differs {
+            self.mask |= 1 << self.bit;
+        }
+        self.bit += 1;
+        differs
+    }
+
+    fn bool(&mut self, v: bool, default: bool) {
+        self.next(v != default);
+    }
+
+    fn f32(&mut self, v: f32, default: f32) {
+        if self.next(v.to_bits() != default.to_bits()) {
+            self.body.extend(v.to_le_bytes());
+        }
+    }
+
+    fn u64(&mut self, v: u64, default: u64) {
+        if self.next(v != default) {
+            put_varint(&mut self.body, v);
+        }
+    }
+
+    fn i64(&mut self, v: i64, default: i64) {
+        // Zigzag.
+        self.u64(
+            ((v << 1) ^ (v >> 63)) as u64,
+            ((default << 1) ^ (default >> 63)) as u64,
+        );
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    mask: u64,
+    bit: u32,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CodeError> {
+        if self.bytes.len() < n {
+            return Err(CodeError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, CodeError> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.take(1)?[0];
+            v |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return Ok(v);
+            }
+        }
+        Err(CodeError::Invalid("varint"))
+    }
+
+    fn next(&mut self) -> bool {
+        let set = self.mask & (1 << self.bit) != 0;
+        self.bit += 1;
+        set
+    }
+
+    fn bool(&mut self, default: bool) -> bool {
+        self.next() != default
+    }
+
+    fn f32(&mut self, default: f32) -> Result<f32, CodeError> {
+        if self.next() {
+            self.raw_f32()
+        } else {
+            Ok(default)
+        }
+    }
+
+    /// A float without a mask bit.
+    fn raw_f32(&mut self) -> Result<f32, CodeError> {
+        let b = self.take(4)?;
+        Ok(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self, default: u64) -> Result<u64, CodeError> {
+        if self.next() {
+            self.varint()
+        } else {
+            Ok(default)
+        }
+    }
+
+    fn i64(&mut self, default: i64) -> Result<i64, CodeError> {
+        let z = self.u64(((default << 1) ^ (default >> 63)) as u64)?;
+        Ok((z >> 1) as i64 ^ -((z & 1) as i64))
+    }
+}
+
+fn waveform_index(waveform: Waveform) -> u64 {
+    Waveform::LIST.iter().position(|w| *w == waveform).unwrap() as u64
+}
+
+impl Asyn {
+    /// The binary share code.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut e = Encoder::default();
+
+        let (p, d) = (&self.pitch, Pitch::default());
+        e.f32(p.frequency, d.frequency);
+        e.f32(p.frequency_sweep, d.frequency_sweep);
+        e.f32(p.frequency_delta_sweep, d.frequency_delta_sweep);
+        e.f32(p.vibrato_depth, d.vibrato_depth);
+        e.f32(p.vibrato_frequency, d.vibrato_frequency);
+        e.f32(p.repeat_frequency, d.repeat_frequency);
+        // The count, then each jump.
+        e.u64(p.frequency_jumps.len() as u64, 0);
+        for (onset, amount) in &p.frequency_jumps {
+            e.body.extend(onset.to_le_bytes());
+            e.body.extend(amount.to_le_bytes());
+        }
+
+        let (t, d) = (&self.tone, Tone::default());
+        e.u64(waveform_index(t.waveform), waveform_index(d.waveform));
+        e.bool(t.interpolate_noise, d.interpolate_noise);
+        e.f32(t.square_duty, d.square_duty);
+        e.f32(t.square_duty_sweep, d.square_duty_sweep);
+        e.u64(t.harmonics as u64, d.harmonics as u64);
+        e.f32(t.harmonics_falloff, d.harmonics_falloff);
+
+        let (a, d) = (&self.amplitude, Amplitude::default());
+        e.f32(a.attack, d.attack);
+        e.f32(a.sustain, d.sustain);
+        e.f32(a.punch, d.punch);
+        e.f32(a.decay, d.decay);
+        e.f32(a.tremolo_depth, d.tremolo_depth);
+        e.f32(a.tremolo_frequency, d.tremolo_frequency);
+
+        e.bool(self.filters.is_some(), false);
+        let (f, d) = (self.filters.clone().unwrap_or_default(), Filters::default());
+        e.f32(f.flanger_offset, d.flanger_offset);
+        e.f32(f.flanger_offset_sweep, d.flanger_offset_sweep);
+        e.i64(f.bit_crush as i64, d.bit_crush as i64);
+        e.i64(f.bit_crush_sweep as i64, d.bit_crush_sweep as i64);
+        e.f32(f.low_pass_cutoff, d.low_pass_cutoff);
+        e.f32(f.low_pass_sweep, d.low_pass_sweep);
+        e.f32(f.high_pass_cutoff, d.high_pass_cutoff);
+        e.f32(f.high_pass_sweep, d.high_pass_sweep);
+        e.f32(f.compression, d.compression);
+        e.bool(f.normalization, d.normalization);
+        e.f32(f.amplification, d.amplification);
+
+        e.bool(self.stereo.is_some(), false);
+        let (s, d) = (self.stereo.unwrap_or_default(), Stereo::default());
+        e.f32(s.pan, d.pan);
+        e.f32(s.pan_sweep, d.pan_sweep);
+        e.f32(s.auto_pan_depth, d.auto_pan_depth);
+        e.f32(s.auto_pan_frequency, d.auto_pan_frequency);
+        e.f32(s.width, d.width);
+
+        e.bool(self.tone.band_limited, Tone::default().band_limited);
+
+        let mut bytes = vec![CODE_VERSION];
+        put_varint(&mut bytes, self.seed);
+        put_varint(&mut bytes, self.mutations as u64);
+        put_varint(&mut bytes, e.mask);
+        bytes.extend(e.body);
+        bytes.extend(crc32(&bytes).to_le_bytes());
+        bytes
+    }
+
+    /// Decode a binary share code.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Asyn, CodeError> {
+        let split = bytes.len().checked_sub(4).ok_or(CodeError::Truncated)?;
+        let (bytes, crc) = bytes.split_at(split);
+        if crc32(bytes).to_le_bytes() != crc {
+            return Err(CodeError::Checksum);
+        }
+
+        let mut d = Decoder {
+            bytes,
+            mask: 0,
+            bit: 0,
+        };
+        let version = d.take(1)?[0];
+        if !(1..=CODE_VERSION).contains(&version) {
+            return Err(CodeError::Version(version));
+        }
+        let seed = d.varint()?;
+        let mutations =
+            usize::try_from(d.varint()?).map_err(|_| CodeError::Invalid("mutations"))?;
+        d.mask = d.varint()?;
+
+        let p = Pitch::default();
+        let mut pitch = Pitch {
+            frequency: d.f32(p.frequency)?,
+            frequency_sweep: d.f32(p.frequency_sweep)?,
+            frequency_delta_sweep: d.f32(p.frequency_delta_sweep)?,
+            vibrato_depth: d.f32(p.vibrato_depth)?,
+            vibrato_frequency: d.f32(p.vibrato_frequency)?,
+            repeat_frequency: d.f32(p.repeat_frequency)?,
+            frequency_jumps: Vec::new(),
+        };
+        if version >= 6 {
+            for _ in 0..d.u64(0)? {
+                pitch.frequency_jumps.push((d.raw_f32()?, d.raw_f32()?));
+            }
+            pitch
+                .check_jumps()
+                .map_err(|_| CodeError::Invalid("frequency jumps"))?;
+        } else {
+            let jumps = [(d.f32(0.33)?, d.f32(0.0)?), (d.f32(0.66)?, d.f32(0.0)?)];
+            pitch = pitch.with_jumps(jumps.into_iter().filter(|(_, amount)| *amount != 0.0));
+        }
+
+        let t = Tone::default();
+        let waveform = d.u64(waveform_index(t.waveform))?;
+        let mut tone = Tone {
+            waveform: *Waveform::LIST
+                .get(waveform as usize)
+                .ok_or(CodeError::Invalid("waveform"))?,
+            interpolate_noise: d.bool(t.interpolate_noise),
+            square_duty: d.f32(t.square_duty)?,
+            square_duty_sweep: d.f32(t.square_duty_sweep)?,
+            harmonics: u32::try_from(d.u64(t.harmonics as u64)?)
+                .ok()
+                .filter(|h| *h <= MAX_HARMONICS)
+                .ok_or(CodeError::Invalid("harmonics"))?,
+            harmonics_falloff: d.f32(t.harmonics_falloff)?,
+            ..Default::default()
+        };
+
+        let a = Amplitude::default();
+        let amplitude = Amplitude {
+            attack: d.f32(a.attack)?,
+            sustain: d.f32(a.sustain)?,
+            punch: d.f32(a.punch)?,
+            decay: d.f32(a.decay)?,
+            tremolo_depth: d.f32(a.tremolo_depth)?,
+            tremolo_frequency: d.f32(a.tremolo_frequency)?,
+        };
+
+        let has_filters = d.bool(false);
+        let f = Filters::default();
+        let mut filters = Filters {
+            flanger_offset: d.f32(f.flanger_offset)?,
+            flanger_offset_sweep: d.f32(f.flanger_offset_sweep)?,
+            bit_crush: i32::try_from(d.i64(f.bit_crush as i64)?)
+                .map_err(|_| CodeError::Invalid("bit crush"))?,
+            bit_crush_sweep: i32::try_from(d.i64(f.bit_crush_sweep as i64)?)
+                .map_err(|_| CodeError::Invalid("bit crush sweep"))?,
+            low_pass_cutoff: d.f32(f.low_pass_cutoff)?,
+            low_pass_sweep: d.f32(f.low_pass_sweep)?,
+            high_pass_cutoff: d.f32(f.high_pass_cutoff)?,
+            high_pass_sweep: d.f32(f.high_pass_sweep)?,
+            compression: d.f32(f.compression)?,
+            normalization: version >= 2 && d.bool(f.normalization),
+            amplification: if version >= 2 {
+                d.f32(f.amplification)?
+            } else {
+                1.0
+            },
+        };
+
+        if version <= 3 {
+            filters.flanger_offset *= 1000.0;
+            filters.flanger_offset_sweep *= 1000.0;
+        }
+
+        // Version 2 codes have no stereo bits set.
+        let has_stereo = d.bool(false);
+        let s = Stereo::default();
+        let stereo = Stereo {
+            pan: d.f32(s.pan)?,
+            pan_sweep: d.f32(s.pan_sweep)?,
+            auto_pan_depth: d.f32(s.auto_pan_depth)?,
+            auto_pan_frequency: d.f32(s.auto_pan_frequency)?,
+            width: d.f32(s.width)?,
+        };
+
+        // Appended, so older codes have it unset.
+        tone.band_limited = d.bool(false);
+
+        if !d.bytes.is_empty() {
+            return Err(CodeError::Invalid("trailing bytes"));
+        }
+
+        Ok(Asyn {
+            seed,
+            mutations,
+            pitch,
+            tone,
+            amplitude,
+            filters: has_filters.then_some(filters),
+            stereo: has_stereo.then_some(stereo),
+        })
+    }
+
+    /// The text share code.
+    pub fn to_code(&self) -> String {
+        base64_encode(&self.to_bytes())
+    }
+
+    /// Decode a text share code. Surrounding whitespace is ignored.
+    pub fn from_code(code: &str) -> Result<Asyn, CodeError> {
+        Self::from_bytes(&base64_decode(code.trim())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::*;
+
+    #[test]
+    fn round_trip() {
+        let rng = &mut funutd::Rnd::from_u64(2);
+        for preset in [blip, explosion, hit, jump, laser, pickup, powerup, random] {
+            for _ in 0..10 {
+                let asyn = preset(rng).mutate(rng);
+                let code = asyn.to_code();
+                assert!(code
+                    .bytes()
+                    .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+                assert_eq!(Asyn::from_code(&code).unwrap(), asyn);
+            }
+        }
+
+        let asyn = Asyn {
+            stereo: Some(Stereo {
+                pan: -0.3,
+                auto_pan_depth: 0.5,
+                width: 0.25,
+                ..Default::default()
+            }),
+            tone: Tone {
+                band_limited: true,
+                ..Default::default()
+            },
+            pitch: Pitch::default().with_jumps([(0.6, 0.5), (0.2, 0.25), (0.4, -0.2)]),
+            ..pickup(rng)
+        };
+        assert_eq!(Asyn::from_code(&asyn.to_code()).unwrap(), asyn);
+
+        // The default sound is the version, three zeros and the checksum.
+        assert_eq!(Asyn::default().to_bytes().len(), 8);
+    }
+
+    #[test]
+    fn same_render() {
+        let rng = &mut funutd::Rnd::from_u64(3);
+        let asyn = explosion(rng);
+        let decoded = Asyn::from_code(&asyn.to_code()).unwrap();
+
+        let a = asyn.to_wav();
+        let b = decoded.to_wav();
+        assert_eq!(a.len(), b.len());
+        assert!((0..a.len()).all(|i| a.at(0, i).to_bits() == b.at(0, i).to_bits()));
+    }
+
+    #[test]
+    fn errors() {
+        let code = laser(&mut funutd::Rnd::from_u64(4)).to_code();
+
+        let mut corrupt = code.clone().into_bytes();
+        corrupt[5] = if corrupt[5] == b'A' { b'B' } else { b'A' };
+        assert_eq!(
+            Asyn::from_code(std::str::from_utf8(&corrupt).unwrap()),
+            Err(CodeError::Checksum)
+        );
+        assert_eq!(Asyn::from_code("not a code"), Err(CodeError::Base64));
+        assert_eq!(Asyn::from_code("AAA"), Err(CodeError::Truncated));
+
+        // Too many harmonics to render.
+        let asyn = Asyn {
+            tone: Tone {
+                harmonics: 1 << 20,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            Asyn::from_code(&asyn.to_code()),
+            Err(CodeError::Invalid("harmonics"))
+        );
+    }
+
+    #[test]
+    fn version1() {
+        let asyn =
+            Asyn::from_code("AQYAwfOogggRuz1D1iZTPm1FHD8AAAAAiwsQP0rzdD_mkT490WiIPgAAAD-41CZU")
+                .unwrap();
+        assert_eq!(asyn.seed, 6);
+        assert_eq!(
+            asyn.filters,
+            Some(Filters {
+                compression: 0.5,
+                normalization: false,
+                ..Default::default()
+            })
+        );
+        assert_eq!(Asyn::from_code(&asyn.to_code()).unwrap(), asyn);
+    }
+
+    #[test]
+    fn version3_flanger() {
+        // Flanger offsets of 2 and -1 ms, in seconds.
+        let asyn = Asyn::from_code("AwAAgICADm8SAztvEoO66iRdEQ").unwrap();
+        let filters = asyn.filters.unwrap();
+        assert_eq!(filters.flanger_offset, 2.0);
+        assert_eq!(filters.flanger_offset_sweep, -1.0);
+    }
+}
@@ -0,0 +1,443 @@
+//! Rust source for preset functions, in the style of [`crate::presets`].
+//!
+//! Fields equal to their defaults are left out. Fixed presets reproduce the sound exactly: floats
+//! are written with the shortest representation that parses back to the same bits.
+
+use std::fmt::Write;
+
+use crate::types::{Amplitude, Asyn, Filters, Pitch, Stereo, Tone, Waveform};
+
+/// A field value, as written in source.
+#[derive(Clone, PartialEq)]
+enum Lit {
+    F32(f32),
+    /// `(onset, amount)` jumps.
+    Jumps(Vec<(f32, f32)>),
+    U32(u32),
+    I32(i32),
+    Bool(bool),
+    Waveform(Waveform),
+}
+
+impl Lit {
+    /// Bitwise equality, so `-0.0` and NaN payloads survive.
+    fn same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::F32(a), Self::F32(b)) => a.to_bits() == b.to_bits(),
+            (Self::Jumps(a), Self::Jumps(b)) => {
+                a.len() == b.len()
+                    && a.iter().zip(b).all(|((a, b), (c, d))| {
+                        a.to_bits() == c.to_bits() && b.to_bits() == d.to_bits()
+                    })
+            }
+            _ => self == other,
+        }
+    }
+}
+
+/// Fields that are fractions of 0-1, and their ranges clamped accordingly.
+const FRACTIONS: [&str; 5] = [
+    "punch",
+    "tremolo_depth",
+    "square_duty",
+    "auto_pan_depth",
+    "width",
+];
+
+fn pitch_fields(p: &Pitch) -> [(&'static str, Lit); 7] {
+    [
+        ("frequency", Lit::F32(p.frequency)),
+        ("frequency_sweep", Lit::F32(p.frequency_sweep)),
+        ("frequency_delta_sweep", Lit::F32(p.frequency_delta_sweep)),
+        ("vibrato_depth", Lit::F32(p.vibrato_depth)),
+        ("vibrato_frequency", Lit::F32(p.vibrato_frequency)),
+        ("repeat_frequency", Lit::F32(p.repeat_frequency)),
+        ("frequency_jumps", Lit::Jumps(p.frequency_jumps.clone())),
+    ]
+}
+
+fn tone_fields(t: &Tone) -> [(&'static str, Lit); 7] {
+    [
+        ("waveform", Lit::Waveform(t.waveform)),
+        ("interpolate_noise", Lit::Bool(t.interpolate_noise)),
+        ("square_duty", Lit::F32(t.square_duty)),
+        ("square_duty_sweep", Lit::F32(t.square_duty_sweep)),
+        ("harmonics", Lit::U32(t.harmonics)),
+        ("harmonics_falloff", Lit::F32(t.harmonics_falloff)),
+        ("band_limited", Lit::Bool(t.band_limited)),
+    ]
+}
+
+fn amplitude_fields(a: &Amplitude) -> [(&'static str, Lit); 6] {
+    [
+        ("attack", Lit::F32(a.attack)),
+        ("sustain", Lit::F32(a.sustain)),
+        ("punch", Lit::F32(a.punch)),
+        ("decay", Lit::F32(a.decay)),
+        ("tremolo_depth", Lit::F32(a.tremolo_depth)),
+        ("tremolo_frequency", Lit::F32(a.tremolo_frequency)),
+    ]
+}
+
+fn filters_fields(f: &Filters) -> [(&'static str, Lit); 11] {
+    [
+        ("flanger_offset", Lit::F32(f.flanger_offset)),
+        ("flanger_offset_sweep", Lit::F32(f.flanger_offset_sweep)),
+        ("bit_crush", Lit::I32(f.bit_crush)),
+        ("bit_crush_sweep", Lit::I32(f.bit_crush_sweep)),
+        ("low_pass_cutoff", Lit::F32(f.low_pass_cutoff)),
+        ("low_pass_sweep", Lit::F32(f.low_pass_sweep)),
+        ("high_pass_cutoff", Lit::F32(f.high_pass_cutoff)),
+        ("high_pass_sweep", Lit::F32(f.high_pass_sweep)),
+        ("compression", Lit::F32(f.compression)),
+        ("normalization", Lit::Bool(f.normalization)),
+        ("amplification", Lit::F32(f.amplification)),
+    ]
+}
+
+fn stereo_fields(s: &Stereo) -> [(&'static str, Lit); 5] {
+    [
+        ("pan", Lit::F32(s.pan)),
+        ("pan_sweep", Lit::F32(s.pan_sweep)),
+        ("auto_pan_depth", Lit::F32(s.auto_pan_depth)),
+        ("auto_pan_frequency", Lit::F32(s.auto_pan_frequency)),
+        ("width", Lit::F32(s.width)),
+    ]
+}
+
+/// The fields of `fields` that differ from `defaults`.
+fn changed<const N: usize>(
+    fields: [(&'static str, Lit); N],
+    defaults: [(&'static str, Lit); N],
+) -> Vec<(&'static str, Lit)> {
+    fields
+        .into_iter()
+        .zip(defaults)
+        .filter(|((_, v), (_, d))| !v.same(d))
+        .map(|(f, _)| f)
+        .collect()
+}
+
+/// A float literal that parses back to exactly `v`, with digit separators in the integer part
+/// like `2_000.0`.
+fn float(v: f32) -> String {
+    if v.is_nan() {
+        return "f32::NAN".into();
+    } else if v.is_infinite() {
+        return if v > 0.0 {
+            "f32::INFINITY"
+        } else {
+            "f32::NEG_INFINITY"
+        }
+        .into();
+    }
+
+    let s = format!("{v:?}");
+    if s.contains('e') {
+        return s;
+    }
+    let (sign, s) = s.split_at(s.starts_with('-') as usize);
+    let (int, frac) = s.split_once('.').unwrap_or((s, "0"));
+    if int.len() < 4 {
+        return format!("{sign}{int}.{frac}");
+    }
+    let mut grouped = String::new();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            grouped.push('_');
+        }
+        grouped.push(c);
+    }
+    format!("{sign}{grouped}.{frac}")
+}
+
+/// `v` rounded to four significant digits, for range bounds.
+fn round4(v: f32) -> f32 {
+    format!("{v:.3e}").parse().unwrap()
+}
+
+/// How to write field values.
+struct Style {
+    /// Relative spread of the random ranges, or `None` for exact values.
+    spread: Option<f32>,
+}
+
+impl Style {
+    fn f32(&self, v: f32, fraction: bool) -> String {
+        let Some(spread) = self.spread.filter(|_| v.is_finite() && v != 0.0) else {
+            return float(v);
+        };
+        let clamp = |x: f32| {
+            if fraction {
+                x.clamp(0.0, 1.0)
+            } else {
+                x
+            }
+        };
+        let (a, b) = (clamp(v * (1.0 - spread)), clamp(v * (1.0 + spread)));
+        format!("rng.f32_in({}, {})", float(round4(a)), float(round4(b)))
+    }
+
+    fn lit(&self, name: &str, lit: &Lit) -> String {
+        let fraction = FRACTIONS.contains(&name);
+        match lit {
+            Lit::F32(v) => self.f32(*v, fraction),
+            // Onsets are kept, so they stay in order.
+            Lit::Jumps(jumps) => {
+                let jumps: Vec<String> = jumps
+                    .iter()
+                    .map(|(onset, amount)| {
+                        format!("({}, {})", float(*onset), self.f32(*amount, false))
+                    })
+                    .collect();
+                format!("vec![{}]", jumps.join(", "))
+            }
+            Lit::U32(v) => v.to_string(),
+            Lit::I32(v) => v.to_string(),
+            Lit::Bool(v) => v.to_string(),
+            Lit::Waveform(w) => format!("{w:?}"),
+        }
+    }
+
+    /// A struct literal field of the changed fields. Left out if there are none, unless wrapped.
+    fn section(
+        &self,
+        out: &mut String,
+        field: &str,
+        ty: &str,
+        fields: Vec<(&'static str, Lit)>,
+        all: usize,
+        wrap: Option<&str>,
+    ) {
+        let (open, close) = wrap.map_or(("", ""), |w| (w, ")"));
+        if fields.is_empty() {
+            if wrap.is_some() {
+                writeln!(out, "        {field}: {open}{ty}::default(){close},").unwrap();
+            }
+            return;
+        }
+
+        writeln!(out, "        {field}: {open}{ty} {{").unwrap();
+        for (name, lit) in &fields {
+            writeln!(out, "            {name}: {},", self.lit(name, lit)).unwrap();
+        }
+        if fields.len() < all {
+            writeln!(out, "            ..Default::default()").unwrap();
+        }
+        writeln!(out, "        }}{close},").unwrap();
+    }
+}
+
+impl Asyn {
+    /// Source for a preset function `name` that returns exactly this sound.
+    pub fn to_preset_source(&self, name: &str) -> String {
+        self.preset_source(name, Style { spread: None })
+    }
+
+    /// Source for a randomized preset function `name`, like those in [`crate::presets`]. Each
+    /// non-default float is picked from a range of `spread` (e.g. 0.2 for ±20%) around its
+    /// current value. The seed comes from the generator.
+    pub fn to_random_preset_source(&self, name: &str, spread: f32) -> String {
+        self.preset_source(
+            name,
+            Style {
+                spread: Some(spread.abs()),
+            },
+        )
+    }
+
+    fn preset_source(&self, name: &str, style: Style) -> String {
+        let pitch = changed(pitch_fields(&self.pitch), pitch_fields(&Pitch::default()));
+        let tone = changed(tone_fields(&self.tone), tone_fields(&Tone::default()));
+        let amplitude = changed(
+            amplitude_fields(&self.amplitude),
+            amplitude_fields(&Amplitude::default()),
+        );
+        let filters = self
+            .filters
+            .as_ref()
+            .map(|f| changed(filters_fields(f), filters_fields(&Filters::default())));
+        let stereo = self
+            .stereo
+            .as_ref()
+            .map(|s| changed(stereo_fields(s), stereo_fields(&Stereo::default())));
+        let random = style.spread.is_some();
+
+        let mut types = vec!["Asyn"];
+        for (used, ty) in [
+            (filters.is_some(), "Filters"),
+            (!pitch.is_empty(), "Pitch"),
+            (stereo.is_some(), "Stereo"),
+            (!tone.is_empty(), "Tone"),
+            (tone.iter().any(|(n, _)| *n == "waveform"), "Waveform"),
+        ] {
+            if used {
+                types.push(ty);
+            }
+        }
+
+        let mut out = String::new();
+        if amplitude.is_empty() && types.len() == 1 {
+            writeln!(out, "use crate::Asyn;").unwrap();
+        } else {
+            if !amplitude.is_empty() {
+                types.insert(0, "types::Amplitude");
+            }
+            writeln!(out, "use crate::{{{}}};", types.join(", ")).unwrap();
+        }
+        writeln!(out).unwrap();
+        if random {
+            writeln!(out, "pub fn {name}(rng: &mut funutd::Rnd) -> Asyn {{").unwrap();
+        } else {
+            writeln!(out, "pub fn {name}() -> Asyn {{").unwrap();
+        }
+        if tone.iter().any(|(n, _)| *n == "waveform") {
+            writeln!(out, "    use Waveform::*;\n").unwrap();
+        }
+        writeln!(out, "    Asyn {{").unwrap();
+
+        let mut omitted = false;
+        if random {
+            writeln!(out, "        seed: rng.stream(),").unwrap();
+        } else if self.seed != 0 {
+            writeln!(out, "        seed: {},", self.seed).unwrap();
+        } else {
+            omitted = true;
+        }
+        // Mutations are history, not sound. Keep them for exact presets only.
+        if !random && self.mutations != 0 {
+            writeln!(out, "        mutations: {},", self.mutations).unwrap();
+        } else {
+            omitted = true;
+        }
+
+        // The field counts come from the field lists, so they can't drift.
+        for (field, ty, fields, all) in [
+            (
+                "pitch",
+                "Pitch",
+                pitch,
+                pitch_fields(&Pitch::default()).len(),
+            ),
+            ("tone", "Tone", tone, tone_fields(&Tone::default()).len()),
+            (
+                "amplitude",
+                "Amplitude",
+                amplitude,
+                amplitude_fields(&Amplitude::default()).len(),
+            ),
+        ] {
+            omitted |= fields.is_empty();
+            style.section(&mut out, field, ty, fields, all, None);
+        }
+        match filters {
+            Some(fields) => {
+                let all = filters_fields(&Filters::default()).len();
+                style.section(&mut out, "filters", "Filters", fields, all, Some("Some("))
+            }
+            None => omitted = true,
+        }
+        match stereo {
+            Some(fields) => {
+                let all = stereo_fields(&Stereo::default()).len();
+                style.section(&mut out, "stereo", "Stereo", fields, all, Some("Some("))
+            }
+            None => omitted = true,
+        }
+
+        if omitted {
+            writeln!(out, "        ..Default::default()").unwrap();
+        }
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_preset() {
+        let asyn = Asyn {
+            seed: 42,
+            pitch: Pitch {
+                frequency: 1234.5,
+                frequency_jumps: vec![(0.25, 0.3), (0.5, -0.1)],
+                ..Default::default()
+            },
+            tone: Tone::from(Waveform::Square),
+            amplitude: Amplitude {
+                sustain: 0.1,
+                decay: -0.0,
+                ..Default::default()
+            },
+            filters: Some(Filters::default()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            asyn.to_preset_source("coin"),
+            "\
+use crate::{types::Amplitude, Asyn, Filters, Pitch, Tone, Waveform};
+
+pub fn coin() -> Asyn {
+    use Waveform::*;
+
+    Asyn {
+        seed: 42,
+        pitch: Pitch {
+            frequency: 1_234.5,
+            frequency_jumps: vec![(0.25, 0.3), (0.5, -0.1)],
+            ..Default::default()
+        },
+        tone: Tone {
+            waveform: Square,
+            ..Default::default()
+        },
+        amplitude: Amplitude {
+            sustain: 0.1,
+            decay: -0.0,
+            ..Default::default()
+        },
+        filters: Some(Filters::default()),
+        ..Default::default()
+    }
+}
+"
+        );
+
+        assert_eq!(
+            Asyn::default().to_preset_source("silence"),
+            "use crate::Asyn;\n\npub fn silence() -> Asyn {\n    Asyn {\n        ..Default::default()\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn float_literals() {
+        let rng = &mut funutd::Rnd::from_u64(5);
+        for v in [0.1, -2000.0, 1e-7, 123_456_790.0, 22_050.0]
+            .into_iter()
+            .chain((0..100).map(|_| rng.f32_in(-30_000.0, 30_000.0)))
+        {
+            let s = float(v).replace('_', "");
+            assert_eq!(s.parse::<f32>().unwrap().to_bits(), v.to_bits(), "{s}");
+        }
+        assert_eq!(float(-2000.0), "-2_000.0");
+        assert_eq!(float(123_456_790.0), "123_456_790.0");
+    }
+
+    #[test]
+    fn random_preset() {
+        let asyn = crate::presets::pickup(&mut funutd::Rnd::from_u64(1));
+        let source = asyn.to_random_preset_source("coin", 0.2);
+        assert!(source.contains("pub fn coin(rng: &mut funutd::Rnd) -> Asyn {"));
+        assert!(source.contains("seed: rng.stream(),"));
+        assert!(source.contains(&format!(
+            "frequency: rng.f32_in({}, {}),",
+            float(round4(asyn.pitch.frequency * 0.8)),
+            float(round4(asyn.pitch.frequency * 1.2))
+        )));
+    }
+}
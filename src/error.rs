@@ -0,0 +1,51 @@
+use std::{error, fmt, io};
+
+use crate::code::CodeError;
+
+/// Errors from rendering and export.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// A parameter is out of range for the output format.
+    Invalid {
+        parameter: &'static str,
+        reason: &'static str,
+    },
+    /// Input that isn't in the expected format.
+    Format(&'static str),
+    /// An embedded share code failed to decode.
+    Code(CodeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "i/o error: {e}"),
+            Self::Invalid { parameter, reason } => write!(f, "invalid {parameter}: {reason}"),
+            Self::Format(what) => write!(f, "invalid input: {what}"),
+            Self::Code(e) => write!(f, "invalid share code: {e}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Code(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<CodeError> for Error {
+    fn from(e: CodeError) -> Self {
+        Self::Code(e)
+    }
+}
@@ -0,0 +1,354 @@
+//! WAV and raw PCM export of rendered sounds.
+//!
+//! Samples are clipped to full scale and rounded. Channels are interleaved. [`dither`] reduces a
+//! wave to an integer format first, with dither instead of plain rounding; [`Asyn`]'s export
+//! methods do so with TPDF dither. This is separate from [`crate::Filters::bit_crush`], which is
+//! part of the sound and never dithered.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use fundsp::hacker32::Wave32;
+
+use crate::{error::Error, types::Asyn};
+
+/// Output sample encoding.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Unsigned 8-bit, centered on 128.
+    U8,
+    /// Signed 8-bit, for raw PCM only. 8-bit WAV is unsigned.
+    I8,
+    I16,
+    I24,
+    F32,
+}
+
+impl SampleFormat {
+    pub fn bytes(self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::I16 => 2,
+            Self::I24 => 3,
+            Self::F32 => 4,
+        }
+    }
+
+    pub fn bits(self) -> u16 {
+        self.bytes() as u16 * 8
+    }
+
+    /// Clip and scale to the integer range of the format (-127 to 127 for 8-bit, before the
+    /// offset). Float samples are scaled to 24 bits.
+    pub(crate) fn to_int(self, x: f32) -> i32 {
+        let x = if x.is_nan() { 0.0 } else { x.clamp(-1.0, 1.0) };
+        let max = match self {
+            Self::U8 | Self::I8 => 127.0,
+            Self::I16 => 32_767.0,
+            Self::I24 | Self::F32 => 8_388_607.0,
+        };
+        (x * max).round() as i32
+    }
+
+    /// Encode one sample, little-endian.
+    pub(crate) fn encode(self, x: f32, out: &mut Vec<u8>) {
+        match self {
+            Self::U8 => out.push((self.to_int(x) + 128) as u8),
+            Self::I8 => out.push(self.to_int(x) as i8 as u8),
+            Self::I16 => out.extend((self.to_int(x) as i16).to_le_bytes()),
+            Self::I24 => out.extend(&self.to_int(x).to_le_bytes()[..3]),
+            Self::F32 => {
+                out.extend(if x.is_nan() { 0.0 } else { x.clamp(-1.0, 1.0) }.to_le_bytes())
+            }
+        }
+    }
+}
+
+/// How [`dither`] reduces samples to an integer format.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Round to the nearest step.
+    None,
+    /// Add triangular (TPDF) noise of up to a step either way before rounding. Quiet decays fade
+    /// into steady noise instead of distorting.
+    #[default]
+    Tpdf,
+    /// TPDF, with the rounding error fed back through a second-order high-pass. The noise is
+    /// louder overall, but moved up above a sixth of the sample rate where it's less audible.
+    Shaped,
+}
+
+/// Reduce `wave` to the steps of an integer `format`, with `dither` seeded by `seed`. Samples are
+/// clipped to full scale. Float is returned as is.
+pub fn dither(wave: &Wave32, format: SampleFormat, dither: Dither, seed: u64) -> Wave32 {
+    let mut output = wave.clone();
+    if format == SampleFormat::F32 {
+        return output;
+    }
+
+    let max = format.to_int(1.0) as f32;
+    for channel in 0..wave.channels() {
+        let rng = &mut funutd::Rnd::from_u64(seed.wrapping_add(channel as u64));
+        // The last two rounding errors, in steps.
+        let mut errors = [0.0f32; 2];
+        for i in 0..wave.len() {
+            let x = wave.at(channel, i);
+            let x = if x.is_nan() {
+                0.0
+            } else {
+                x.clamp(-1.0, 1.0) * max
+            };
+            let y = match dither {
+                Dither::Shaped => x + 2.0 * errors[0] - errors[1],
+                _ => x,
+            };
+            let noise = match dither {
+                Dither::None => 0.0,
+                _ => rng.f32() - rng.f32(),
+            };
+            let q = (y + noise).round();
+            errors = [y - q, errors[0]];
+            output.set(channel, i, q.clamp(-max, max) / max);
+        }
+    }
+    output
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+fn invalid(parameter: &'static str, reason: &'static str) -> Error {
+    Error::Invalid { parameter, reason }
+}
+
+/// Interleaved sample data.
+fn encode(wave: &Wave32, format: SampleFormat, endian: Endian) -> Result<Vec<u8>, Error> {
+    if wave.channels() == 0 {
+        return Err(invalid("channels", "no channels"));
+    }
+
+    let mut data = Vec::with_capacity(wave.len() * wave.channels() * format.bytes());
+    for i in 0..wave.len() {
+        for channel in 0..wave.channels() {
+            format.encode(wave.at(channel, i), &mut data);
+        }
+    }
+    if endian == Endian::Big {
+        for sample in data.chunks_exact_mut(format.bytes()) {
+            sample.reverse();
+        }
+    }
+    Ok(data)
+}
+
+/// Write headerless PCM.
+pub fn write_pcm(
+    wave: &Wave32,
+    format: SampleFormat,
+    endian: Endian,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    Ok(writer.write_all(&encode(wave, format, endian)?)?)
+}
+
+/// Write a WAV file. Float samples use the IEEE float format tag, the rest are PCM.
+pub fn write_wav(wave: &Wave32, format: SampleFormat, mut writer: impl Write) -> Result<(), Error> {
+    if format == SampleFormat::I8 {
+        return Err(invalid("format", "8-bit WAV is unsigned"));
+    }
+    let sample_rate = wave.sample_rate();
+    if !(sample_rate >= 1.0 && sample_rate <= u32::MAX as f64 && sample_rate.fract() == 0.0) {
+        return Err(invalid(
+            "sample rate",
+            "not a positive whole number of hertz",
+        ));
+    }
+    let sample_rate = sample_rate as u32;
+    let channels =
+        u16::try_from(wave.channels()).map_err(|_| invalid("channels", "more than 65535"))?;
+    let block_align = channels
+        .checked_mul(format.bytes() as u16)
+        .ok_or(invalid("channels", "too many for the sample format"))?;
+    let byte_rate = sample_rate
+        .checked_mul(block_align as u32)
+        .ok_or(invalid("sample rate", "over 4 GiB per second"))?;
+
+    let data = encode(wave, format, Endian::Little)?;
+    let float = format == SampleFormat::F32;
+    // Non-PCM formats have an extension size in fmt and a fact chunk.
+    let fmt_len: u32 = if float { 18 } else { 16 };
+    let fact_len: u32 = if float { 12 } else { 0 };
+    let pad = data.len() % 2;
+    let riff_len = (4 + 8 + fmt_len + fact_len) as u64 + 8 + (data.len() + pad) as u64;
+    let riff_len = u32::try_from(riff_len).map_err(|_| invalid("length", "over 4 GiB"))?;
+
+    let mut header = Vec::with_capacity(58);
+    header.extend(b"RIFF");
+    header.extend(riff_len.to_le_bytes());
+    header.extend(b"WAVEfmt ");
+    header.extend(fmt_len.to_le_bytes());
+    header.extend((if float { 3u16 } else { 1u16 }).to_le_bytes());
+    header.extend(channels.to_le_bytes());
+    header.extend(sample_rate.to_le_bytes());
+    header.extend(byte_rate.to_le_bytes());
+    header.extend(block_align.to_le_bytes());
+    header.extend(format.bits().to_le_bytes());
+    if float {
+        header.extend(0u16.to_le_bytes());
+        header.extend(b"fact");
+        header.extend(4u32.to_le_bytes());
+        header.extend((wave.len() as u32).to_le_bytes());
+    }
+    header.extend(b"data");
+    header.extend((data.len() as u32).to_le_bytes());
+
+    writer.write_all(&header)?;
+    writer.write_all(&data)?;
+    // Chunks are word aligned.
+    writer.write_all(&[0; 1][..pad])?;
+    Ok(())
+}
+
+impl Asyn {
+    /// Render and [`dither`] for `format`, seeded with the sound's seed.
+    pub fn export_wave(&self, format: SampleFormat, dither: Dither) -> Wave32 {
+        self::dither(&self.clone().to_wav(), format, dither, self.seed)
+    }
+
+    /// Render and write a WAV file, with TPDF dither.
+    pub fn write_wav(&self, format: SampleFormat, writer: impl Write) -> Result<(), Error> {
+        write_wav(&self.export_wave(format, Dither::Tpdf), format, writer)
+    }
+
+    /// Render and save a WAV file.
+    pub fn save_wav(&self, path: impl AsRef<Path>, format: SampleFormat) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_wav(format, &mut writer)?;
+        Ok(writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wave() -> Wave32 {
+        Wave32::from_samples(8_000.0, &[0.0, 0.5, -1.0, 2.0, f32::NAN])
+    }
+
+    #[test]
+    fn formats() {
+        for (format, fmt_len) in [
+            (SampleFormat::U8, 16),
+            (SampleFormat::I16, 16),
+            (SampleFormat::I24, 16),
+            (SampleFormat::F32, 18),
+        ] {
+            let mut wav = Vec::new();
+            write_wav(&wave(), format, &mut wav).unwrap();
+
+            let u16_at = |i: usize| u16::from_le_bytes([wav[i], wav[i + 1]]);
+            let u32_at =
+                |i: usize| u32::from_le_bytes([wav[i], wav[i + 1], wav[i + 2], wav[i + 3]]);
+            assert_eq!(&wav[..4], b"RIFF");
+            assert_eq!(u32_at(4) as usize, wav.len() - 8);
+            assert_eq!(u32_at(16), fmt_len);
+            assert_eq!(u16_at(34), format.bits());
+            assert_eq!(u32_at(24), 8_000);
+            assert_eq!(wav.len() % 2, 0);
+
+            let data = 20 + fmt_len as usize + if fmt_len > 16 { 12 } else { 0 };
+            assert_eq!(&wav[data..data + 4], b"data");
+            assert_eq!(u32_at(data + 4) as usize, 5 * format.bytes());
+        }
+
+        let mut pcm = Vec::new();
+        write_pcm(&wave(), SampleFormat::U8, Endian::Little, &mut pcm).unwrap();
+        assert_eq!(pcm, [128, 192, 1, 255, 128]);
+
+        let mut pcm = Vec::new();
+        write_pcm(&wave(), SampleFormat::I8, Endian::Little, &mut pcm).unwrap();
+        assert_eq!(pcm, [0, 64, 0x81, 127, 0]);
+        assert!(write_wav(&wave(), SampleFormat::I8, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn endianness() {
+        let (mut le, mut be) = (Vec::new(), Vec::new());
+        write_pcm(&wave(), SampleFormat::I24, Endian::Little, &mut le).unwrap();
+        write_pcm(&wave(), SampleFormat::I24, Endian::Big, &mut be).unwrap();
+        assert_eq!(&le[3..6], [0x00, 0x00, 0x40]);
+        assert_eq!(&be[3..6], [0x40, 0x00, 0x00]);
+        assert_eq!(&be[6..9], [0x80, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn dithering() {
+        // A constant a third of an 8-bit step.
+        let x = 1.0 / 127.0 / 3.0;
+        let wave = Wave32::from_samples(44_100.0, &[x; 44_100]);
+        let steps = |dither| -> Vec<f32> {
+            let wave = super::dither(&wave, SampleFormat::U8, dither, 1);
+            wave.channel(0).iter().map(|y| y * 127.0).collect()
+        };
+        let mean = |steps: &[f32]| steps.iter().sum::<f32>() / steps.len() as f32;
+
+        // Rounding loses it.
+        assert!(steps(Dither::None).iter().all(|y| *y == 0.0));
+
+        for dither in [Dither::Tpdf, Dither::Shaped] {
+            let steps = steps(dither);
+            assert!(steps.iter().all(|y| (y - y.round()).abs() < 1e-4));
+            // It's there on average.
+            assert!((mean(&steps) - 1.0 / 3.0).abs() < 0.02, "{dither:?}");
+        }
+
+        // Shaping leaves less noise at low frequencies, seen here in short averages.
+        let low_noise = |dither| {
+            let steps = steps(dither);
+            let averages: Vec<f32> = steps.chunks(256).map(mean).collect();
+            averages
+                .iter()
+                .map(|a| (a - 1.0 / 3.0).powi(2))
+                .sum::<f32>()
+        };
+        assert!(low_noise(Dither::Shaped) < 0.1 * low_noise(Dither::Tpdf));
+
+        // Float is left alone.
+        let float = dither(&wave, SampleFormat::F32, Dither::Shaped, 1);
+        assert_eq!(float.at(0, 100), x);
+    }
+
+    #[test]
+    fn errors() {
+        let mut short = [0u8; 10];
+        assert!(matches!(
+            write_wav(&wave(), SampleFormat::I16, &mut short[..]),
+            Err(Error::Io(_))
+        ));
+
+        let wave = Wave32::from_samples(44_100.5, &[0.0]);
+        assert!(matches!(
+            write_wav(&wave, SampleFormat::I16, Vec::new()),
+            Err(Error::Invalid {
+                parameter: "sample rate",
+                ..
+            })
+        ));
+        // The byte rate doesn't fit in the header.
+        let wave = Wave32::from_samples(2_000_000_000.0, &[0.0, 0.0]);
+        assert!(matches!(
+            write_wav(&wave, SampleFormat::F32, Vec::new()),
+            Err(Error::Invalid {
+                parameter: "sample rate",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,487 @@
+//! FLAC export.
+//!
+//! A small encoder: fixed-size blocks of independent channels, each coded as constant, verbatim
+//! or with the best fixed predictor and Rice partitioning. The share code goes in an `ASYN_CODE`
+//! Vorbis comment, which [`Asyn::from_flac`] reads back. The STREAMINFO MD5 is left unset, which
+//! the format allows.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+use fundsp::hacker32::Wave32;
+
+use crate::{
+    error::Error,
+    export::{Dither, SampleFormat},
+    types::Asyn,
+};
+
+/// The Vorbis comment holding the share code.
+pub const FLAC_CODE_TAG: &str = "ASYN_CODE";
+
+const BLOCK_SIZE: usize = 4096;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_PARTITION_ORDER: u32 = 8;
+
+/// MSB-first bit writer.
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    acc: u64,
+    n: u32,
+}
+
+impl Bits {
+    /// Write the low `bits` (at most 32) bits of `v`.
+    fn put(&mut self, v: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.acc = (self.acc << bits) | (v & ((1 << bits) - 1));
+        self.n += bits;
+        while self.n >= 8 {
+            self.n -= 8;
+            self.bytes.push((self.acc >> self.n) as u8);
+        }
+    }
+
+    fn put_signed(&mut self, v: i64, bits: u32) {
+        self.put(v as u64, bits);
+    }
+
+    /// `q` zeros and a one.
+    fn unary(&mut self, mut q: u64) {
+        while q >= 32 {
+            self.put(0, 32);
+            q -= 32;
+        }
+        self.put(1, q as u32 + 1);
+    }
+
+    /// Zero-pad to a byte boundary.
+    fn align(&mut self) {
+        if self.n > 0 {
+            self.put(0, 8 - self.n);
+        }
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, b| {
+        (0..8).fold(crc ^ b, |crc, _| {
+            (crc << 1) ^ if crc & 0x80 != 0 { 0x07 } else { 0 }
+        })
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, b| {
+        (0..8).fold(crc ^ (*b as u16) << 8, |crc, _| {
+            (crc << 1) ^ if crc & 0x8000 != 0 { 0x8005 } else { 0 }
+        })
+    })
+}
+
+/// The UTF-8-like coding of frame numbers.
+fn put_utf8(bits: &mut Bits, v: u64) {
+    if v < 0x80 {
+        bits.put(v, 8);
+        return;
+    }
+    // Continuation bytes carry six bits each, the first byte 6 - n.
+    let n = (1..=6).find(|&n| v < 1 << (6 * n + 6 - n)).unwrap();
+    let lead = !0u8 << (7 - n);
+    bits.put((lead as u64) | (v >> (6 * n)), 8);
+    for i in (0..n).rev() {
+        bits.put(0x80 | ((v >> (6 * i)) & 0x3f), 8);
+    }
+}
+
+fn zigzag(r: i64) -> u64 {
+    ((r << 1) ^ (r >> 63)) as u64
+}
+
+/// Residuals of the fixed predictor of `order`.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    samples
+        .windows(order + 1)
+        .map(|w| {
+            let s = |i: usize| w[order - i];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+/// The best Rice parameter for a partition, and its size in bits (without the parameter).
+fn rice_parameter(residual: &[i64], max: u32) -> (u32, u64) {
+    (0..=max)
+        .map(|k| {
+            let bits = residual
+                .iter()
+                .map(|r| (zigzag(*r) >> k) + 1 + k as u64)
+                .sum::<u64>();
+            (k, bits)
+        })
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap()
+}
+
+/// Rice coding of a residual: method, partition order and per-partition parameters.
+struct Rice {
+    method: u32,
+    order: u32,
+    parameters: Vec<u32>,
+    bits: u64,
+}
+
+impl Rice {
+    fn best(residual: &[i64], block: usize, predictor: usize) -> Self {
+        (0..=MAX_PARTITION_ORDER)
+            .take_while(|&o| block.is_multiple_of(1 << o) && block >> o > predictor)
+            .map(|order| {
+                let len = block >> order;
+                let mut start = 0;
+                let (mut parameters, mut bits) = (Vec::new(), 0);
+                for p in 0..1 << order {
+                    let n = if p == 0 { len - predictor } else { len };
+                    let (k, b) = rice_parameter(&residual[start..start + n], 30);
+                    parameters.push(k);
+                    bits += b;
+                    start += n;
+                }
+                // Parameters over 14 need the five-bit escape of the second method.
+                let method = parameters.iter().any(|k| *k > 14) as u32;
+                bits += parameters.len() as u64 * (4 + method as u64);
+                Self {
+                    method,
+                    order,
+                    parameters,
+                    bits: bits + 6,
+                }
+            })
+            .min_by_key(|r| r.bits)
+            .unwrap()
+    }
+
+    fn write(&self, bits: &mut Bits, residual: &[i64], block: usize, predictor: usize) {
+        bits.put(self.method as u64, 2);
+        bits.put(self.order as u64, 4);
+        let param_bits = if self.method == 1 { 5 } else { 4 };
+        let len = block >> self.order;
+        let mut start = 0;
+        for (p, k) in self.parameters.iter().enumerate() {
+            let n = if p == 0 { len - predictor } else { len };
+            bits.put(*k as u64, param_bits);
+            for r in &residual[start..start + n] {
+                let u = zigzag(*r);
+                bits.unary(u >> k);
+                bits.put(u, *k);
+            }
+            start += n;
+        }
+    }
+}
+
+fn write_subframe(bits: &mut Bits, samples: &[i64], bps: u32) {
+    // Header: zero pad bit, type, no wasted bits.
+    if samples.iter().all(|s| *s == samples[0]) {
+        bits.put(0, 8);
+        bits.put_signed(samples[0], bps);
+        return;
+    }
+
+    let verbatim = samples.len() as u64 * bps as u64;
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let rice = Rice::best(&residual, samples.len(), order);
+            (order, residual, rice)
+        })
+        .min_by_key(|(order, _, rice)| *order as u64 * bps as u64 + rice.bits);
+
+    match best {
+        Some((order, residual, rice)) if order as u64 * bps as u64 + rice.bits < verbatim => {
+            bits.put(0b0001_0000 | (order as u64) << 1, 8);
+            for s in &samples[..order] {
+                bits.put_signed(*s, bps);
+            }
+            rice.write(bits, &residual, samples.len(), order);
+        }
+        _ => {
+            bits.put(0b0000_0010, 8);
+            for s in samples {
+                bits.put_signed(*s, bps);
+            }
+        }
+    }
+}
+
+/// Write a FLAC stream. `format` must be 16 or 24 bit. Vorbis comments are `(name, value)`.
+pub fn write_flac(
+    wave: &Wave32,
+    format: SampleFormat,
+    comments: &[(&str, &str)],
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let invalid = |parameter, reason| Error::Invalid { parameter, reason };
+
+    let bps = match format {
+        SampleFormat::I16 => 16,
+        SampleFormat::I24 => 24,
+        _ => return Err(invalid("format", "FLAC is 16 or 24 bit")),
+    };
+    let sample_rate = wave.sample_rate();
+    if !((1.0..=655_350.0).contains(&sample_rate) && sample_rate.fract() == 0.0) {
+        return Err(invalid(
+            "sample rate",
+            "not a whole number of hertz up to 655350",
+        ));
+    }
+    let channels = wave.channels();
+    if !(1..=8).contains(&channels) {
+        return Err(invalid("channels", "FLAC has 1 to 8 channels"));
+    }
+    let len = wave.len() as u64;
+    if len >= 1 << 36 {
+        return Err(invalid("length", "too long"));
+    }
+
+    writer.write_all(b"fLaC")?;
+
+    // STREAMINFO. The block size is fixed, apart from the last block.
+    let mut info = Bits::default();
+    let block = if len > 0 && len < BLOCK_SIZE as u64 {
+        len.max(16)
+    } else {
+        BLOCK_SIZE as u64
+    };
+    info.put(block, 16);
+    info.put(block, 16);
+    // Frame sizes are unknown.
+    info.put(0, 24);
+    info.put(0, 24);
+    info.put(sample_rate as u64, 20);
+    info.put(channels as u64 - 1, 3);
+    info.put(bps as u64 - 1, 5);
+    info.put(len >> 32, 4);
+    info.put(len & 0xffff_ffff, 32);
+    // MD5, unset.
+    info.put(0, 32);
+    info.put(0, 32);
+    info.put(0, 32);
+    info.put(0, 32);
+    writer.write_all(&[0, 0, 0, 34])?;
+    writer.write_all(&info.bytes)?;
+
+    // VORBIS_COMMENT, the last metadata block. Its fields are little-endian.
+    let mut comment = Vec::new();
+    let vendor = concat!("asyn ", env!("CARGO_PKG_VERSION"));
+    comment.extend((vendor.len() as u32).to_le_bytes());
+    comment.extend(vendor.as_bytes());
+    comment.extend((comments.len() as u32).to_le_bytes());
+    for (name, value) in comments {
+        let field = format!("{name}={value}");
+        comment.extend((field.len() as u32).to_le_bytes());
+        comment.extend(field.as_bytes());
+    }
+    if comment.len() >= 1 << 24 {
+        return Err(invalid("comments", "too long"));
+    }
+    writer.write_all(&(0x8400_0000 | comment.len() as u32).to_be_bytes())?;
+    writer.write_all(&comment)?;
+
+    let samples: Vec<Vec<i64>> = (0..channels)
+        .map(|c| {
+            (0..wave.len())
+                .map(|i| format.to_int(wave.at(c, i)) as i64)
+                .collect()
+        })
+        .collect();
+
+    for (number, start) in (0..wave.len()).step_by(BLOCK_SIZE).enumerate() {
+        let n = BLOCK_SIZE.min(wave.len() - start);
+        let mut bits = Bits::default();
+
+        // Sync code, fixed block size.
+        bits.put(0b1111_1111_1111_1000, 16);
+        let size_code = if n == BLOCK_SIZE { 0b1100 } else { 0b0111 };
+        // Sample rate from STREAMINFO.
+        bits.put(size_code << 4, 8);
+        let size_bits = if bps == 16 { 0b100 } else { 0b110 };
+        bits.put(((channels as u64 - 1) << 4) | size_bits << 1, 8);
+        put_utf8(&mut bits, number as u64);
+        if n != BLOCK_SIZE {
+            bits.put(n as u64 - 1, 16);
+        }
+        let crc = crc8(&bits.bytes);
+        bits.put(crc as u64, 8);
+
+        for channel in &samples {
+            write_subframe(&mut bits, &channel[start..start + n], bps);
+        }
+        bits.align();
+        let crc = crc16(&bits.bytes);
+        bits.put(crc as u64, 16);
+        writer.write_all(&bits.bytes)?;
+    }
+
+    Ok(())
+}
+
+impl Asyn {
+    /// Render and write a FLAC file, with TPDF dither and the share code in a Vorbis comment.
+    pub fn write_flac(&self, format: SampleFormat, writer: impl Write) -> Result<(), Error> {
+        let code = self.to_code();
+        write_flac(
+            &self.export_wave(format, Dither::Tpdf),
+            format,
+            &[(FLAC_CODE_TAG, &code)],
+            writer,
+        )
+    }
+
+    /// Render and save a FLAC file.
+    pub fn save_flac(&self, path: impl AsRef<Path>, format: SampleFormat) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_flac(format, &mut writer)?;
+        Ok(writer.flush()?)
+    }
+
+    /// The sound embedded in a FLAC file written by [`Asyn::write_flac`]. Only the metadata is
+    /// read.
+    pub fn from_flac(mut reader: impl Read) -> Result<Asyn, Error> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"fLaC" {
+            return Err(Error::Format("not a FLAC file"));
+        }
+
+        loop {
+            let mut header = [0; 4];
+            reader.read_exact(&mut header)?;
+            let last = header[0] & 0x80 != 0;
+            let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+            let mut block = vec![0; len];
+            reader.read_exact(&mut block)?;
+
+            if header[0] & 0x7f == 4 {
+                if let Some(code) = vorbis_comment(&block, FLAC_CODE_TAG) {
+                    return Ok(Asyn::from_code(code)?);
+                }
+            }
+            if last {
+                return Err(Error::Format("no share code in FLAC file"));
+            }
+        }
+    }
+}
+
+/// The value of the first comment `name` (case insensitive) in a VORBIS_COMMENT block.
+fn vorbis_comment<'a>(block: &'a [u8], name: &str) -> Option<&'a str> {
+    fn u32_at(rest: &mut &[u8]) -> Option<usize> {
+        let (n, tail) = rest.split_first_chunk::<4>()?;
+        *rest = tail;
+        Some(u32::from_le_bytes(*n) as usize)
+    }
+    fn field<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let len = u32_at(rest)?;
+        let (field, tail) = rest.split_at_checked(len)?;
+        *rest = tail;
+        Some(field)
+    }
+
+    let mut rest = block;
+    // Vendor, then the count.
+    field(&mut rest)?;
+    let count = u32_at(&mut rest)?;
+    (0..count).find_map(|_| {
+        let (n, value) = std::str::from_utf8(field(&mut rest)?)
+            .ok()?
+            .split_once('=')?;
+        n.eq_ignore_ascii_case(name).then_some(value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(flac: &[u8]) -> (claxon::metadata::StreamInfo, Vec<i32>) {
+        let mut reader = claxon::FlacReader::new(flac).unwrap();
+        let samples = reader.samples().map(Result::unwrap).collect();
+        (reader.streaminfo(), samples)
+    }
+
+    #[test]
+    fn lossless() {
+        let rng = &mut funutd::Rnd::from_u64(12);
+        for (format, bits) in [(SampleFormat::I16, 16), (SampleFormat::I24, 24)] {
+            for preset in [crate::presets::explosion, crate::presets::laser] {
+                let asyn = preset(rng);
+                let wave = asyn.export_wave(format, Dither::Tpdf);
+                let mut flac = Vec::new();
+                asyn.write_flac(format, &mut flac).unwrap();
+
+                let (info, samples) = decode(&flac);
+                assert_eq!(info.bits_per_sample, bits);
+                assert_eq!(info.sample_rate, 44_100);
+                assert_eq!(info.samples, Some(wave.len() as u64));
+                assert!(samples
+                    .iter()
+                    .enumerate()
+                    .all(|(i, s)| *s == format.to_int(wave.at(0, i))));
+                // It does compress.
+                assert!(flac.len() < wave.len() * format.bytes());
+
+                assert_eq!(Asyn::from_flac(&flac[..]).unwrap(), asyn);
+            }
+        }
+    }
+
+    #[test]
+    fn edge_cases() {
+        // Silence, a single sample, full scale and a partial block.
+        for samples in [
+            vec![0.0; 5000],
+            vec![0.25],
+            (0..4200)
+                .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+                .collect(),
+        ] {
+            let wave = Wave32::from_samples(8_000.0, &samples);
+            let mut flac = Vec::new();
+            write_flac(&wave, SampleFormat::I16, &[("TITLE", "test")], &mut flac).unwrap();
+
+            let (_, decoded) = decode(&flac);
+            let expected: Vec<i32> = samples
+                .iter()
+                .map(|x| SampleFormat::I16.to_int(*x))
+                .collect();
+            assert_eq!(decoded, expected);
+            assert!(matches!(Asyn::from_flac(&flac[..]), Err(Error::Format(_))));
+        }
+
+        assert!(matches!(
+            write_flac(
+                &Wave32::new(1, 44_100.0),
+                SampleFormat::F32,
+                &[],
+                Vec::new()
+            ),
+            Err(Error::Invalid {
+                parameter: "format",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,554 @@
+//! Import and export of jfxr (https://jfxr.frozenfractal.com) sound files.
+//!
+//! jfxr stores percentages (punch, tremolo depth, jump onsets/amounts, square duty, amplification)
+//! as 0-100; we store them as fractions. Everything else uses the same units, including flanger
+//! offsets in milliseconds. The seed and mutation count are kept in underscore (metadata) keys,
+//! which jfxr ignores.
+
+use std::{error, fmt};
+
+use serde_json::{json, Map, Value};
+
+use crate::types::{Amplitude, Asyn, Filters, Pitch, Tone, Waveform, MAX_HARMONICS};
+
+/// The only jfxr file version there is.
+pub const JFXR_VERSION: u64 = 1;
+
+/// jfxr renders at this rate unless told otherwise.
+const JFXR_SAMPLE_RATE: f64 = 44_100.0;
+
+const FILTER_KEYS: [&str; 9] = [
+    "flangerOffset",
+    "flangerOffsetSweep",
+    "bitCrush",
+    "bitCrushSweep",
+    "lowPassCutoff",
+    "lowPassCutoffSweep",
+    "highPassCutoff",
+    "highPassCutoffSweep",
+    "compression",
+];
+
+const KEYS: [&str; 25] = [
+    "sampleRate",
+    "attack",
+    "sustain",
+    "sustainPunch",
+    "decay",
+    "tremoloDepth",
+    "tremoloFrequency",
+    "frequency",
+    "frequencySweep",
+    "frequencyDeltaSweep",
+    "repeatFrequency",
+    "frequencyJump1Onset",
+    "frequencyJump1Amount",
+    "frequencyJump2Onset",
+    "frequencyJump2Amount",
+    "harmonics",
+    "harmonicsFalloff",
+    "waveform",
+    "interpolateNoise",
+    "vibratoDepth",
+    "vibratoFrequency",
+    "squareDuty",
+    "squareDutySweep",
+    "normalization",
+    "amplification",
+];
+
+/// A parameter that was not (or not entirely) carried over.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JfxrWarning {
+    /// A jfxr parameter with no counterpart in [`Asyn`]. The sound will not render the same.
+    Unsupported { name: &'static str, value: Value },
+    /// A parameter jfxr doesn't know about either.
+    Unknown { name: String, value: Value },
+    /// An [`Asyn`] parameter (or the part of one) that jfxr has no counterpart for, left out of
+    /// an export.
+    Dropped { name: &'static str, value: Value },
+}
+
+impl fmt::Display for JfxrWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported { name, value } => write!(f, "unsupported parameter {name}: {value}"),
+            Self::Unknown { name, value } => write!(f, "unknown parameter {name}: {value}"),
+            Self::Dropped { name, value } => write!(f, "dropped parameter {name}: {value}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JfxrError {
+    Json(serde_json::Error),
+    /// The document is not a JSON object.
+    NotAnObject,
+    /// The `_version` is not one we can read.
+    Version(Value),
+    /// A known parameter has the wrong type or an out of range value.
+    InvalidValue {
+        name: &'static str,
+        value: Value,
+    },
+}
+
+impl fmt::Display for JfxrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid jfxr json: {e}"),
+            Self::NotAnObject => write!(f, "jfxr document is not an object"),
+            Self::Version(v) => write!(f, "unsupported jfxr version: {v}"),
+            Self::InvalidValue { name, value } => write!(f, "invalid value for {name}: {value}"),
+        }
+    }
+}
+
+impl error::Error for JfxrError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for JfxrError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+/// jfxr waveform names.
+pub fn jfxr_waveform(waveform: Waveform) -> &'static str {
+    match waveform {
+        Waveform::Sine => "sine",
+        Waveform::Triangle => "triangle",
+        Waveform::Saw => "sawtooth",
+        Waveform::Square => "square",
+        Waveform::Tangent => "tangent",
+        Waveform::Whistle => "whistle",
+        Waveform::Breaker => "breaker",
+        Waveform::White => "whitenoise",
+        Waveform::Pink => "pinknoise",
+        Waveform::Brown => "brownnoise",
+    }
+}
+
+fn waveform_from_jfxr(name: &str) -> Option<Waveform> {
+    use Waveform::*;
+
+    [
+        Sine, Triangle, Saw, Square, Tangent, Whistle, Breaker, White, Pink, Brown,
+    ]
+    .into_iter()
+    .find(|w| jfxr_waveform(*w) == name)
+}
+
+/// Reads parameters from a jfxr document, falling back to jfxr defaults for missing ones.
+struct Params<'a>(&'a Map<String, Value>);
+
+impl<'a> Params<'a> {
+    fn get(&self, name: &'static str) -> Option<&'a Value> {
+        self.0.get(name)
+    }
+
+    fn f64(&self, name: &'static str, default: f64) -> Result<f64, JfxrError> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(value) => value.as_f64().ok_or_else(|| JfxrError::InvalidValue {
+                name,
+                value: value.clone(),
+            }),
+        }
+    }
+
+    fn f32(&self, name: &'static str, default: f32) -> Result<f32, JfxrError> {
+        self.f64(name, default as f64).map(|v| v as f32)
+    }
+
+    /// Percent to fraction.
+    fn percent(&self, name: &'static str, default: f32) -> Result<f32, JfxrError> {
+        self.f64(name, default as f64 * 100.0)
+            .map(|v| (v / 100.0) as f32)
+    }
+
+    fn i32(&self, name: &'static str, default: i32) -> Result<i32, JfxrError> {
+        self.f64(name, default as f64).map(|v| v.round() as i32)
+    }
+
+    fn u32(&self, name: &'static str, default: u32) -> Result<u32, JfxrError> {
+        match self.f64(name, default as f64)? {
+            v if v >= 0.0 => Ok(v.round() as u32),
+            _ => Err(self.invalid(name)),
+        }
+    }
+
+    fn bool(&self, name: &'static str, default: bool) -> Result<bool, JfxrError> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(value) => value.as_bool().ok_or_else(|| self.invalid(name)),
+        }
+    }
+
+    fn u64(&self, name: &'static str, default: u64) -> Result<u64, JfxrError> {
+        match self.get(name) {
+            None => Ok(default),
+            // Large seeds don't survive as javascript numbers, so they are written as strings.
+            Some(Value::String(s)) => s.parse().map_err(|_| self.invalid(name)),
+            Some(value) => value.as_u64().ok_or_else(|| self.invalid(name)),
+        }
+    }
+
+    fn invalid(&self, name: &'static str) -> JfxrError {
+        JfxrError::InvalidValue {
+            name,
+            value: self.get(name).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+impl Asyn {
+    /// Parse a jfxr sound file. Parameters that can't be carried over are returned as warnings.
+    pub fn from_jfxr(json: &str) -> Result<(Asyn, Vec<JfxrWarning>), JfxrError> {
+        let value: Value = serde_json::from_str(json)?;
+        let map = value.as_object().ok_or(JfxrError::NotAnObject)?;
+        let p = Params(map);
+
+        match map.get("_version") {
+            None => (),
+            Some(v) if v.as_u64() == Some(JFXR_VERSION) => (),
+            Some(v) => return Err(JfxrError::Version(v.clone())),
+        }
+
+        let mut warnings = Vec::new();
+
+        // Things jfxr does that we don't (yet).
+        if p.f64("sampleRate", JFXR_SAMPLE_RATE)? != JFXR_SAMPLE_RATE {
+            warnings.push(JfxrWarning::Unsupported {
+                name: "sampleRate",
+                value: map["sampleRate"].clone(),
+            });
+        }
+
+        // Metadata (_name, _locked, etc.) is skipped.
+        for (name, value) in map {
+            if !name.starts_with('_')
+                && !KEYS.contains(&name.as_str())
+                && !FILTER_KEYS.contains(&name.as_str())
+            {
+                warnings.push(JfxrWarning::Unknown {
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+
+        let default = Pitch::default();
+        // jfxr always has two jumps. Those that do nothing are left out. Down to -100% leaves no
+        // frequency, which Pitch doesn't allow.
+        let amount = |name| match p.percent(name, 0.0)? {
+            a if a > -1.0 => Ok(a),
+            _ => Err(p.invalid(name)),
+        };
+        let jumps = [
+            (
+                p.percent("frequencyJump1Onset", 0.33)?,
+                amount("frequencyJump1Amount")?,
+            ),
+            (
+                p.percent("frequencyJump2Onset", 0.66)?,
+                amount("frequencyJump2Amount")?,
+            ),
+        ];
+        let pitch = Pitch {
+            frequency: p.f32("frequency", default.frequency)?,
+            frequency_sweep: p.f32("frequencySweep", default.frequency_sweep)?,
+            frequency_delta_sweep: p.f32("frequencyDeltaSweep", default.frequency_delta_sweep)?,
+            vibrato_depth: p.f32("vibratoDepth", default.vibrato_depth)?,
+            vibrato_frequency: p.f32("vibratoFrequency", default.vibrato_frequency)?,
+            repeat_frequency: p.f32("repeatFrequency", default.repeat_frequency)?,
+            ..default
+        }
+        .with_jumps(jumps.into_iter().filter(|(_, amount)| *amount != 0.0));
+
+        let default = Tone::default();
+        let tone = Tone {
+            waveform: match p.get("waveform") {
+                None => default.waveform,
+                Some(v) => v
+                    .as_str()
+                    .and_then(waveform_from_jfxr)
+                    .ok_or_else(|| p.invalid("waveform"))?,
+            },
+            interpolate_noise: p.bool("interpolateNoise", default.interpolate_noise)?,
+            square_duty: p.percent("squareDuty", default.square_duty)?,
+            square_duty_sweep: p.percent("squareDutySweep", default.square_duty_sweep)?,
+            // jfxr's own slider stops at the same limit.
+            harmonics: match p.u32("harmonics", default.harmonics)? {
+                h if h <= MAX_HARMONICS => h,
+                _ => return Err(p.invalid("harmonics")),
+            },
+            harmonics_falloff: p.f32("harmonicsFalloff", default.harmonics_falloff)?,
+            ..default
+        };
+
+        let amplitude = Amplitude {
+            attack: p.f32("attack", 0.0)?,
+            sustain: p.f32("sustain", 0.0)?,
+            punch: p.percent("sustainPunch", 0.0)?,
+            decay: p.f32("decay", 0.0)?,
+            tremolo_depth: p.percent("tremoloDepth", 0.0)?,
+            tremolo_frequency: p.f32("tremoloFrequency", 10.0)?,
+        };
+
+        // Filters are left off unless the file mentions them, or changes the gain.
+        let default = Filters::default();
+        let normalization = p.bool("normalization", default.normalization)?;
+        let amplification = p.percent("amplification", default.amplification)?;
+        let filters = if FILTER_KEYS.iter().any(|k| map.contains_key(*k))
+            || normalization
+            || amplification != 1.0
+        {
+            Some(Filters {
+                flanger_offset: p.f32("flangerOffset", default.flanger_offset)?,
+                flanger_offset_sweep: p.f32("flangerOffsetSweep", default.flanger_offset_sweep)?,
+                bit_crush: p.i32("bitCrush", default.bit_crush)?,
+                bit_crush_sweep: p.i32("bitCrushSweep", default.bit_crush_sweep)?,
+                low_pass_cutoff: p.f32("lowPassCutoff", default.low_pass_cutoff)?,
+                low_pass_sweep: p.f32("lowPassCutoffSweep", default.low_pass_sweep)?,
+                high_pass_cutoff: p.f32("highPassCutoff", default.high_pass_cutoff)?,
+                high_pass_sweep: p.f32("highPassCutoffSweep", default.high_pass_sweep)?,
+                compression: p.f32("compression", default.compression)?,
+                normalization,
+                amplification,
+            })
+        } else {
+            None
+        };
+
+        let asyn = Asyn {
+            seed: p.u64("_seed", 0)?,
+            mutations: p.u64("_mutations", 0)? as usize,
+            pitch,
+            tone,
+            amplitude,
+            filters,
+            stereo: None,
+        };
+
+        Ok((asyn, warnings))
+    }
+
+    /// Write a jfxr sound file. Filter parameters are left out if there are no filters. jfxr is
+    /// mono, so stereo is dropped, and its oscillators are never band-limited. jfxr has two jumps,
+    /// so only the first two are written. What is dropped is returned as warnings.
+    pub fn to_jfxr(&self) -> (String, Vec<JfxrWarning>) {
+        // Fraction to percent. This (and the inverse) is exact in f64.
+        let percent = |f: f32| f as f64 * 100.0;
+
+        let Asyn {
+            seed,
+            mutations,
+            pitch: p,
+            tone: t,
+            amplitude: a,
+            filters,
+            stereo,
+        } = self;
+
+        let mut warnings = Vec::new();
+        if let Some(stereo) = stereo {
+            warnings.push(JfxrWarning::Dropped {
+                name: "stereo",
+                value: json!(stereo.to_string()),
+            });
+        }
+        if t.band_limited {
+            warnings.push(JfxrWarning::Dropped {
+                name: "band_limited",
+                value: json!(true),
+            });
+        }
+        if let Some(rest) = p.frequency_jumps.get(2..).filter(|rest| !rest.is_empty()) {
+            warnings.push(JfxrWarning::Dropped {
+                name: "frequency_jumps",
+                value: json!(rest),
+            });
+        }
+
+        // No filters, no gain.
+        let (normalization, amplification) = filters
+            .as_ref()
+            .map_or((false, 1.0), |f| (f.normalization, f.amplification));
+
+        // Unused jumps are jfxr's defaults.
+        let jump = |i: usize, onset| p.frequency_jumps.get(i).copied().unwrap_or((onset, 0.0));
+        let (jump1, jump2) = (jump(0, 0.33), jump(1, 0.66));
+
+        let mut json = json!({
+            "_version": JFXR_VERSION,
+            "_name": "asyn",
+            "_locked": [],
+            "_seed": seed.to_string(),
+            "_mutations": mutations,
+            "sampleRate": JFXR_SAMPLE_RATE,
+            "attack": a.attack,
+            "sustain": a.sustain,
+            "sustainPunch": percent(a.punch),
+            "decay": a.decay,
+            "tremoloDepth": percent(a.tremolo_depth),
+            "tremoloFrequency": a.tremolo_frequency,
+            "frequency": p.frequency,
+            "frequencySweep": p.frequency_sweep,
+            "frequencyDeltaSweep": p.frequency_delta_sweep,
+            "repeatFrequency": p.repeat_frequency,
+            "frequencyJump1Onset": percent(jump1.0),
+            "frequencyJump1Amount": percent(jump1.1),
+            "frequencyJump2Onset": percent(jump2.0),
+            "frequencyJump2Amount": percent(jump2.1),
+            "harmonics": t.harmonics,
+            "harmonicsFalloff": t.harmonics_falloff,
+            "waveform": jfxr_waveform(t.waveform),
+            "interpolateNoise": t.interpolate_noise,
+            "vibratoDepth": p.vibrato_depth,
+            "vibratoFrequency": p.vibrato_frequency,
+            "squareDuty": percent(t.square_duty),
+            "squareDutySweep": percent(t.square_duty_sweep),
+            "normalization": normalization,
+            "amplification": percent(amplification),
+        });
+
+        if let (Some(f), Some(map)) = (filters, json.as_object_mut()) {
+            map.extend(
+                [
+                    ("flangerOffset", json!(f.flanger_offset)),
+                    ("flangerOffsetSweep", json!(f.flanger_offset_sweep)),
+                    ("bitCrush", json!(f.bit_crush)),
+                    ("bitCrushSweep", json!(f.bit_crush_sweep)),
+                    ("lowPassCutoff", json!(f.low_pass_cutoff)),
+                    ("lowPassCutoffSweep", json!(f.low_pass_sweep)),
+                    ("highPassCutoff", json!(f.high_pass_cutoff)),
+                    ("highPassCutoffSweep", json!(f.high_pass_sweep)),
+                    ("compression", json!(f.compression)),
+                ]
+                .map(|(k, v)| (k.to_string(), v)),
+            );
+        }
+
+        (json.to_string(), warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pickup/coin preset saved from the jfxr web editor.
+    const PICKUP: &str = r#"{"_version":1,"_name":"Pickup/coin 1","_locked":[],"sampleRate":44100,"attack":0,"sustain":0.04,"sustainPunch":50,"decay":0.2,"tremoloDepth":0,"tremoloFrequency":10,"frequency":1200,"frequencySweep":0,"frequencyDeltaSweep":0,"repeatFrequency":0,"frequencyJump1Onset":25,"frequencyJump1Amount":30,"frequencyJump2Onset":66,"frequencyJump2Amount":0,"harmonics":0,"harmonicsFalloff":0.5,"waveform":"square","interpolateNoise":true,"vibratoDepth":0,"vibratoFrequency":10,"squareDuty":40,"squareDutySweep":0,"flangerOffset":0,"flangerOffsetSweep":0,"bitCrush":16,"bitCrushSweep":0,"lowPassCutoff":22050,"lowPassCutoffSweep":0,"highPassCutoff":0,"highPassCutoffSweep":0,"compression":1,"normalization":true,"amplification":100}"#;
+
+    #[test]
+    fn import_pickup() -> Result<(), crate::Error> {
+        let (asyn, warnings) = Asyn::from_jfxr(PICKUP).unwrap();
+
+        assert_eq!(asyn.pitch.frequency, 1200.0);
+        assert_eq!(asyn.pitch.frequency_jumps, [(0.25, 0.3)]);
+        assert_eq!(asyn.tone.waveform, Waveform::Square);
+        assert_eq!(asyn.tone.square_duty, 0.4);
+        assert_eq!(asyn.amplitude.sustain, 0.04);
+        assert_eq!(asyn.amplitude.punch, 0.5);
+        assert_eq!(asyn.filters.as_ref().map(|f| f.bit_crush), Some(16));
+        assert_eq!(asyn.filters.as_ref().map(|f| f.normalization), Some(true));
+        assert!(warnings.is_empty(), "{warnings:?}");
+
+        asyn.save_wav("jfxr_pickup.wav", crate::SampleFormat::I16)
+    }
+
+    #[test]
+    fn import_errors() {
+        let (asyn, warnings) = Asyn::from_jfxr(r#"{"frequency":440,"reverb":1}"#).unwrap();
+        assert_eq!(asyn.pitch.frequency, 440.0);
+        // jfxr normalizes unless told otherwise.
+        assert_eq!(asyn.filters, Some(Filters::default()));
+        assert!(matches!(&warnings[..], [JfxrWarning::Unknown { name, .. }] if name == "reverb"));
+
+        let (asyn, _) = Asyn::from_jfxr(r#"{"normalization":false,"amplification":100}"#).unwrap();
+        assert!(asyn.filters.is_none());
+
+        assert!(matches!(
+            Asyn::from_jfxr(r#"{"waveform":"kazoo"}"#),
+            Err(JfxrError::InvalidValue {
+                name: "waveform",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Asyn::from_jfxr(r#"{"_version":2}"#),
+            Err(JfxrError::Version(_))
+        ));
+        assert!(matches!(Asyn::from_jfxr("[]"), Err(JfxrError::NotAnObject)));
+        assert!(matches!(
+            Asyn::from_jfxr(r#"{"frequencyJump2Amount":-100}"#),
+            Err(JfxrError::InvalidValue {
+                name: "frequencyJump2Amount",
+                ..
+            })
+        ));
+        assert!(matches!(
+            Asyn::from_jfxr(r#"{"harmonics":6}"#),
+            Err(JfxrError::InvalidValue {
+                name: "harmonics",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn export_warnings() {
+        // An arpeggio, up a major third, a fifth and an octave.
+        let asyn = Asyn {
+            pitch: Pitch::default().with_jumps([(0.25, 0.26), (0.5, 0.19), (0.75, 0.33)]),
+            ..Default::default()
+        };
+        let (json, warnings) = asyn.to_jfxr();
+        assert_eq!(
+            warnings,
+            [JfxrWarning::Dropped {
+                name: "frequency_jumps",
+                value: json!([[0.75f32, 0.33f32]]),
+            }]
+        );
+        let (imported, _) = Asyn::from_jfxr(&json).unwrap();
+        assert_eq!(
+            imported.pitch.frequency_jumps,
+            asyn.pitch.frequency_jumps[..2]
+        );
+    }
+
+    #[test]
+    fn round_trip_presets() {
+        use crate::presets::*;
+
+        let presets: [fn(&mut funutd::Rnd) -> Asyn; 8] =
+            [blip, explosion, hit, jump, laser, pickup, powerup, random];
+
+        let rng = &mut funutd::Rnd::from_u64(0);
+        for preset in presets {
+            for _ in 0..20 {
+                let mut asyn = preset(rng);
+                // Mutation can gain jumps, which must survive too.
+                while rng.bool(0.6) {
+                    asyn = asyn.mutate(rng);
+                }
+
+                let (json, dropped) = asyn.to_jfxr();
+                // Jumps past the second are dropped, with a warning.
+                assert_eq!(dropped.is_empty(), asyn.pitch.frequency_jumps.len() <= 2);
+                asyn.pitch.frequency_jumps.truncate(2);
+                let (imported, warnings) = Asyn::from_jfxr(&json).unwrap();
+                assert_eq!(imported, asyn);
+                assert!(warnings.is_empty(), "{warnings:?}");
+            }
+        }
+    }
+}
@@ -1,5 +1,24 @@
+mod bank;
+mod batch;
+mod blocks;
+mod cache;
+mod code;
+mod codegen;
+mod error;
+mod export;
+mod flac;
+mod jfxr;
+mod loudness;
+mod note;
 mod osc;
+mod oversample;
+mod parse;
 mod play;
+mod profile;
+mod qoa;
+#[cfg(feature = "serde")]
+mod schema;
+mod sfxr;
 mod types;
 pub mod presets {
     pub mod blip;
@@ -19,10 +38,63 @@ pub mod presets {
     pub use pickup::*;
     pub use powerup::*;
     pub use random::*;
+
+    use crate::{Asyn, Scale};
+
+    /// A preset generator.
+    pub type Preset = fn(&mut funutd::Rnd) -> Asyn;
+
+    /// Every preset, by name.
+    pub const PRESETS: [(&str, Preset); 8] = [
+        ("blip", blip),
+        ("explosion", explosion),
+        ("hit", hit),
+        ("jump", jump),
+        ("laser", laser),
+        ("pickup", pickup),
+        ("powerup", powerup),
+        ("random", random),
+    ];
+
+    /// Look up a preset by name.
+    pub fn preset(name: &str) -> Option<Preset> {
+        PRESETS.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+    }
+
+    /// Generate a sound with `preset`, snapped to `scale` if there is one (see
+    /// [`crate::Pitch::snap`]). The same seed gives the same sound as the preset, but in key.
+    pub fn generate(preset: Preset, rng: &mut funutd::Rnd, scale: Option<&Scale>) -> Asyn {
+        let asyn = preset(rng);
+        match scale {
+            Some(scale) => Asyn {
+                pitch: asyn.pitch.snap(scale),
+                ..asyn
+            },
+            None => asyn,
+        }
+    }
 }
 
+pub use bank::*;
+pub use batch::*;
+pub use blocks::*;
+pub use cache::*;
+pub use code::*;
+pub use error::*;
+pub use export::*;
+pub use flac::*;
+pub use jfxr::*;
+pub use loudness::*;
+pub use note::*;
 pub use osc::*;
+pub use oversample::*;
+pub use parse::*;
 pub use play::*;
+pub use profile::*;
+pub use qoa::*;
+#[cfg(feature = "serde")]
+pub use schema::*;
+pub use sfxr::*;
 pub use types::*;
 
 #[cfg(test)]
@@ -30,7 +102,7 @@ mod tests {
     use crate::presets::*;
 
     #[test]
-    fn it_works() {
+    fn it_works() -> Result<(), crate::Error> {
         //let len = 0.25;
         //let mut jump = (constant(22.0) | constant(0.5)) >> harmonic(osc::square(), 3, 0.5);
 
@@ -55,7 +127,7 @@ mod tests {
         let rng = &mut funutd::Rnd::from_u64(seed);
         println!("seed: {}", seed);
 
-        random(rng).to_wav().save_wav16("test.wav").unwrap();
+        random(rng).save_wav("test.wav", crate::SampleFormat::I16)
         //wav.write_wav16(&mut std::io::stdout().lock()).unwrap();
     }
 }
@@ -0,0 +1,251 @@
+//! Loudness measurement, per ITU-R BS.1770-4.
+//!
+//! Integrated loudness is the K-weighted mean square of each channel, summed and gated in 400 ms
+//! blocks. True peak is the peak of the wave upsampled by four. Many sound effects are shorter
+//! than a block, so those are measured as a single block of their whole length.
+
+use std::f64::consts::PI;
+
+use fundsp::hacker32::Wave32;
+
+use crate::oversample;
+
+/// Blocks whose loudness is under this are silence.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks this far under the ungated loudness are left out.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// The upsampling factor for true peak.
+const TRUE_PEAK_FACTOR: usize = 4;
+
+/// The highest true peak [`normalize_loudness`] will scale to, in dBTP.
+pub const TRUE_PEAK_CEILING: f32 = -1.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS. Negative infinity if the wave is silent.
+    pub integrated: f32,
+    /// True peak in dBTP.
+    pub true_peak: f32,
+}
+
+/// A biquad in direct form I.
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn tick(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// The K-weighting filters, a high shelf then a high-pass, for any sample rate. The analog
+/// prototypes are from libebur128, and match the coefficients in BS.1770 at 48 kHz.
+fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+    let biquad = |b: [f64; 3], a: [f64; 3]| Biquad {
+        b: b.map(|b| b / a[0]),
+        a: [a[1] / a[0], a[2] / a[0]],
+        x: [0.0; 2],
+        y: [0.0; 2],
+    };
+
+    let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / sample_rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = biquad(
+        [
+            vh + vb * k / q + k * k,
+            2.0 * (k * k - vh),
+            vh - vb * k / q + k * k,
+        ],
+        [
+            1.0 + k / q + k * k,
+            2.0 * (k * k - 1.0),
+            1.0 - k / q + k * k,
+        ],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / sample_rate).tan();
+    let high_pass = biquad(
+        [1.0, -2.0, 1.0],
+        [
+            1.0 + k / q + k * k,
+            2.0 * (k * k - 1.0),
+            1.0 - k / q + k * k,
+        ],
+    );
+
+    [shelf, high_pass]
+}
+
+fn lufs(mean_square: f64) -> f64 {
+    -0.691 + 10.0 * mean_square.log10()
+}
+
+/// Integrated loudness in LUFS. Every channel has a weight of one, as for mono and stereo.
+fn integrated(wave: &Wave32) -> f64 {
+    let block = (0.4 * wave.sample_rate()).round() as usize;
+    let step = (0.1 * wave.sample_rate()).round() as usize;
+    let (block, blocks) = if wave.len() < block {
+        (wave.len(), 1)
+    } else {
+        (block, (wave.len() - block) / step + 1)
+    };
+    if block == 0 {
+        return f64::NEG_INFINITY;
+    }
+
+    // Per-block sums of squares, over the channels.
+    let mut sums = vec![0.0; blocks];
+    for channel in 0..wave.channels() {
+        let [mut shelf, mut high_pass] = k_weighting(wave.sample_rate());
+        let squares: Vec<f64> = wave
+            .channel(channel)
+            .iter()
+            .map(|x| high_pass.tick(shelf.tick(*x as f64)).powi(2))
+            .collect();
+        for (j, sum) in sums.iter_mut().enumerate() {
+            *sum += squares[j * step..j * step + block].iter().sum::<f64>();
+        }
+    }
+    let powers: Vec<f64> = sums.iter().map(|s| s / block as f64).collect();
+
+    let gated = |threshold: f64| {
+        let above: Vec<f64> = powers
+            .iter()
+            .copied()
+            .filter(|p| lufs(*p) > threshold)
+            .collect();
+        (!above.is_empty()).then(|| above.iter().sum::<f64>() / above.len() as f64)
+    };
+    let Some(power) = gated(ABSOLUTE_GATE) else {
+        return f64::NEG_INFINITY;
+    };
+    gated(lufs(power) + RELATIVE_GATE)
+        .map(lufs)
+        .unwrap_or(f64::NEG_INFINITY)
+}
+
+/// The peak of every channel upsampled by [`TRUE_PEAK_FACTOR`], as a linear level.
+fn true_peak(wave: &Wave32) -> f32 {
+    let kernel = oversample::kernel(TRUE_PEAK_FACTOR);
+    let half = kernel.len() / 2;
+    let mut peak = 0.0f32;
+    for channel in 0..wave.channels() {
+        let input = wave.channel(channel);
+        // Each output sample is the kernel centered on it, over the input with zeros stuffed
+        // between samples.
+        for n in 0..input.len() * TRUE_PEAK_FACTOR {
+            let first = (n + TRUE_PEAK_FACTOR - 1).saturating_sub(half) / TRUE_PEAK_FACTOR;
+            let last = ((n + half) / TRUE_PEAK_FACTOR).min(input.len() - 1);
+            let y: f32 = (first..=last)
+                .map(|i| input[i] * kernel[i * TRUE_PEAK_FACTOR + half - n])
+                .sum();
+            peak = peak.max((y * TRUE_PEAK_FACTOR as f32).abs());
+        }
+    }
+    peak
+}
+
+/// Measure the loudness of a render.
+pub fn loudness(wave: &Wave32) -> Loudness {
+    Loudness {
+        integrated: integrated(wave) as f32,
+        true_peak: 20.0 * true_peak(wave).log10(),
+    }
+}
+
+/// Scale `wave` to an integrated loudness of `target` LUFS, but no further than a true peak of
+/// [`TRUE_PEAK_CEILING`], so loud targets on peaky sounds come out quieter rather than clipped.
+/// Returns the integrated loudness reached. Silence is left alone.
+pub fn normalize_loudness(wave: &mut Wave32, target: f32) -> f32 {
+    let loudness = integrated(wave);
+    if !loudness.is_finite() {
+        return loudness as f32;
+    }
+    let ceiling = 10f64.powf(TRUE_PEAK_CEILING as f64 / 20.0) / true_peak(wave) as f64;
+    let scale = 10f64.powf((target as f64 - loudness) / 20.0).min(ceiling);
+    for channel in 0..wave.channels() {
+        for i in 0..wave.len() {
+            wave.set(channel, i, wave.at(channel, i) * scale as f32);
+        }
+    }
+    (loudness + 20.0 * scale.log10()) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{presets::*, types::RenderOptions};
+    use fundsp::hacker32::sine_hz;
+
+    #[test]
+    fn reference_levels() {
+        // A full scale 997 Hz sine in one channel is -3.01 LUFS.
+        let wave = Wave32::render(48_000.0, 2.0, &mut sine_hz(997.0));
+        let l = loudness(&wave);
+        assert!((l.integrated + 3.01).abs() < 0.05, "{l:?}");
+        assert!(l.true_peak.abs() < 0.05, "{l:?}");
+
+        // Samples of a quarter-rate sine at 45° all miss the peak by 3 dB.
+        let mut wave = Wave32::new(0, 48_000.0);
+        let samples: Vec<f32> = (0..4800)
+            .map(|i| (PI / 2.0 * i as f64 + PI / 4.0).sin() as f32)
+            .collect();
+        wave.push_channel(&samples);
+        assert!((20.0 * wave.amplitude().log10() + 3.01).abs() < 0.01);
+        // Less the ringing from starting abruptly.
+        assert!(loudness(&wave).true_peak.abs() < 0.2);
+
+        assert_eq!(
+            loudness(&Wave32::render(
+                48_000.0,
+                0.1,
+                &mut fundsp::hacker32::zero()
+            ))
+            .integrated,
+            f32::NEG_INFINITY
+        );
+    }
+
+    #[test]
+    fn normalize() {
+        let rng = &mut funutd::Rnd::from_u64(21);
+        for asyn in [explosion(rng), blip(rng), laser(rng)] {
+            let wave = asyn.render(&RenderOptions {
+                loudness: Some(-16.0),
+                ..Default::default()
+            });
+            // Either at the target, or short of it at the ceiling.
+            let l = loudness(&wave);
+            assert!(l.true_peak < TRUE_PEAK_CEILING + 0.01, "{l:?}");
+            assert!(
+                (l.integrated + 16.0).abs() < 0.01
+                    || (l.integrated < -16.0 && l.true_peak > TRUE_PEAK_CEILING - 0.01),
+                "{l:?}"
+            );
+        }
+
+        // Too loud for the ceiling.
+        let mut wave = explosion(rng).to_wav();
+        let reached = normalize_loudness(&mut wave, -3.0);
+        let l = loudness(&wave);
+        assert!(
+            reached < -3.0 && (l.integrated - reached).abs() < 0.01,
+            "{l:?}"
+        );
+        assert!((l.true_peak - TRUE_PEAK_CEILING).abs() < 0.01, "{l:?}");
+    }
+}
@@ -0,0 +1,230 @@
+//! Notes, semitones and scales.
+//!
+//! Notes are MIDI note numbers, where 60 is middle C (C4) and 69 is A4 at 440 Hz, in equal
+//! temperament. They are `f32` so they can fall between keys. A [`Scale`] snaps a [`Pitch`] to
+//! its degrees, e.g. to keep generated sounds in a game's key (see [`crate::presets::generate`]).
+
+use std::fmt;
+
+use crate::{
+    parse::{self, ParseError},
+    types::Pitch,
+};
+
+/// The frequency of a MIDI note number.
+pub fn midi_to_hz(note: f32) -> f32 {
+    440.0 * ((note - 69.0) / 12.0).exp2()
+}
+
+/// The MIDI note number of a frequency.
+pub fn hz_to_midi(hz: f32) -> f32 {
+    69.0 + 12.0 * (hz / 440.0).log2()
+}
+
+/// A jump amount (see [`Pitch::frequency_jumps`]) of `n` semitones.
+pub fn semitones(n: f32) -> f32 {
+    (n / 12.0).exp2() - 1.0
+}
+
+/// Pitch class names, with sharps.
+pub(crate) const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Major,
+    Minor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Chromatic,
+}
+
+impl Mode {
+    /// Every mode, by name, as parsed and displayed.
+    pub const NAMES: [(&'static str, Mode); 5] = [
+        ("major pentatonic", Mode::MajorPentatonic),
+        ("minor pentatonic", Mode::MinorPentatonic),
+        ("major", Mode::Major),
+        ("minor", Mode::Minor),
+        ("chromatic", Mode::Chromatic),
+    ];
+
+    /// Semitones above the key of each degree in an octave.
+    pub fn intervals(self) -> &'static [u8] {
+        match self {
+            Mode::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Mode::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Mode::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Mode::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Mode::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|(_, m)| *m == self).unwrap().0
+    }
+}
+
+/// A key and mode, e.g. `"F# minor"` parsed with [`str::parse`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Scale {
+    /// The pitch class of the key, from C = 0 to B = 11.
+    pub key: u8,
+    pub mode: Mode,
+}
+
+impl Scale {
+    pub fn new(key: u8, mode: Mode) -> Self {
+        Self {
+            key: key % 12,
+            mode,
+        }
+    }
+
+    /// The nearest note in the scale. Ties go down.
+    pub fn snap(&self, note: f32) -> f32 {
+        let note = note - self.key as f32;
+        let octave = (note / 12.0).floor();
+        let within = note - octave * 12.0;
+        // The key an octave up is a candidate too.
+        let degree = self
+            .mode
+            .intervals()
+            .iter()
+            .map(|i| *i as f32)
+            .chain([12.0])
+            .min_by(|a, b| (a - within).abs().total_cmp(&(b - within).abs()))
+            .unwrap();
+        self.key as f32 + octave * 12.0 + degree
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", NOTE_NAMES[self.key as usize], self.mode.name())
+    }
+}
+
+impl Pitch {
+    /// The default pitch at MIDI note `note`.
+    pub fn from_midi(note: f32) -> Self {
+        Self {
+            frequency: midi_to_hz(note),
+            ..Default::default()
+        }
+    }
+
+    /// The default pitch at a note name such as `"C4"`, `"F#3"` or `"Bb-1"`.
+    pub fn from_note(name: &str) -> Result<Self, ParseError> {
+        parse::note(name).map(Self::from_midi)
+    }
+
+    /// The MIDI note number of the starting frequency.
+    pub fn midi(&self) -> f32 {
+        hz_to_midi(self.frequency)
+    }
+
+    /// Set the jumps from `(onset, semitones)` pairs, sorted by onset.
+    pub fn with_semitone_jumps(self, jumps: impl IntoIterator<Item = (f32, f32)>) -> Self {
+        self.with_jumps(jumps.into_iter().map(|(onset, n)| (onset, semitones(n))))
+    }
+
+    /// Snap the starting frequency, and the frequency after each jump, to the nearest notes in
+    /// `scale`. Sweeps, vibrato and repeats are left alone, so can still move off the scale. A
+    /// jump to zero or negative frequency has no note, so it and those after it are left alone.
+    pub fn snap(mut self, scale: &Scale) -> Self {
+        if !(self.frequency.is_finite() && self.frequency > 0.0) {
+            return self;
+        }
+        let mut note = self.midi();
+        let mut snapped = scale.snap(note);
+        self.frequency = midi_to_hz(snapped);
+        for (_, amount) in &mut self.frequency_jumps {
+            let factor = 1.0 + *amount;
+            if !(factor.is_finite() && factor > 0.0) {
+                break;
+            }
+            note += 12.0 * factor.log2();
+            let next = scale.snap(note);
+            *amount = semitones(next - snapped);
+            snapped = next;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn notes() {
+        assert_eq!(midi_to_hz(69.0), 440.0);
+        assert!(close(midi_to_hz(60.0), 261.626));
+        assert!(close(hz_to_midi(880.0), 81.0));
+        assert!(close(semitones(12.0), 1.0));
+        assert!(close(semitones(-12.0), -0.5));
+
+        assert_eq!(Pitch::from_note("A4").unwrap().frequency, 440.0);
+        assert!(close(Pitch::from_note("Bb3").unwrap().midi(), 58.0));
+        assert!(close(Pitch::from_note("C-1").unwrap().midi(), 0.0));
+        assert!(close(Pitch::from_note("F#3").unwrap().midi(), 54.0));
+        assert!(Pitch::from_note("H2").is_err());
+        assert!(Pitch::from_note("C").is_err());
+
+        // A major arpeggio.
+        let pitch = Pitch::from_midi(60.0).with_semitone_jumps([(0.5, 3.0), (0.25, 4.0)]);
+        assert!(close(pitch.frequency_jumps[0].1, semitones(4.0)));
+        let parsed: Pitch = "C4 jumps: (0.25, 0.26) (0.50, 0.19)".parse().unwrap();
+        assert!(close(parsed.midi(), 60.0));
+        assert_eq!(parsed.frequency_jumps.len(), 2);
+    }
+
+    #[test]
+    fn scales() {
+        let scale: Scale = "F# minor".parse().unwrap();
+        assert_eq!(scale, Scale::new(6, Mode::Minor));
+        assert_eq!(scale.to_string(), "F# minor");
+        assert_eq!(
+            "Bb major pentatonic".parse(),
+            Ok(Scale::new(10, Mode::MajorPentatonic))
+        );
+        assert!("C lydian".parse::<Scale>().is_err());
+
+        let c_major = Scale::new(0, Mode::Major);
+        assert_eq!(c_major.snap(61.4), 62.0);
+        assert_eq!(c_major.snap(61.0), 60.0);
+        assert_eq!(c_major.snap(71.6), 72.0);
+        assert_eq!(c_major.snap(-0.8), -1.0);
+
+        // C#4 snaps to D4. Up 6.6 semitones from C#4 is nearest A4, and down a fifth from
+        // there is nearest D4 again.
+        let pitch = Pitch::from_midi(61.0)
+            .with_jumps([(0.2, semitones(6.6)), (0.6, semitones(-7.0))])
+            .snap(&Scale::new(2, Mode::MajorPentatonic));
+        assert!(close(pitch.midi(), 62.0));
+        assert!(close(pitch.frequency_jumps[0].1, semitones(7.0)));
+        assert!(close(pitch.frequency_jumps[1].1, semitones(-7.0)));
+
+        let rng = &mut funutd::Rnd::from_u64(25);
+        for (_, preset) in PRESETS {
+            let asyn = generate(preset, rng, Some(&c_major));
+            let mut note = asyn.pitch.midi();
+            assert!(close(c_major.snap(note), note));
+            for (_, amount) in &asyn.pitch.frequency_jumps {
+                // Snapping stops at a jump to zero or negative frequency.
+                if *amount <= -1.0 {
+                    break;
+                }
+                note += 12.0 * (1.0 + amount).log2();
+                assert!((c_major.snap(note) - note).abs() < 0.01);
+            }
+        }
+    }
+}
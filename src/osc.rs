@@ -2,6 +2,8 @@ use fundsp::hacker32::*;
 use funutd::Rnd;
 use numeric_array::*;
 
+// Like fundsp's oscillators, these start at DEFAULT_SR and take the actual sample rate on reset.
+
 pub fn square() -> An<Square<f32>> {
     An(Square::new(DEFAULT_SR))
 }
@@ -253,6 +255,282 @@ where
     }
 }
 
+/// Band-limited square oscillator (PolyBLEP). Takes the same inputs as [`square`].
+pub fn blep_square() -> An<BlepSquare<f32>> {
+    An(BlepSquare::new(DEFAULT_SR))
+}
+
+/// Band-limited [`saw`] (PolyBLEP).
+pub fn blep_saw() -> An<impl AudioNode<Sample = f32, Inputs = U1, Outputs = U1>> {
+    An(BlepOsc::new(DEFAULT_SR, |phase, dt| {
+        let naive = if phase < 0.5 {
+            2.0 * phase
+        } else {
+            -2.0 + 2.0 * phase
+        };
+        // It falls by 2 at half phase.
+        naive - poly_blep(wrap_phase(phase + 0.5), dt)
+    }))
+}
+
+/// Band-limited [`triangle`] (PolyBLAMP).
+pub fn blep_triangle() -> An<impl AudioNode<Sample = f32, Inputs = U1, Outputs = U1>> {
+    An(BlepOsc::new(DEFAULT_SR, |phase, dt| {
+        let naive = if phase < 0.25 {
+            4.0 * phase
+        } else if phase < 0.75 {
+            2.0 - 4.0 * phase
+        } else {
+            -4.0 + 4.0 * phase
+        };
+        // The slope changes by 8 per cycle at the peak and trough.
+        naive
+            + 8.0
+                * dt
+                * (poly_blamp(wrap_phase(phase + 0.25), dt)
+                    - poly_blamp(wrap_phase(phase + 0.75), dt))
+    }))
+}
+
+fn wrap_phase<T: Real>(phase: T) -> T {
+    if phase >= T::one() {
+        phase - T::one()
+    } else {
+        phase
+    }
+}
+
+/// The residual of a band-limited step of 2 (from -1 to 1) at phase 0, for a phase increment of
+/// `dt` per sample.
+fn poly_blep<T: Real>(t: T, dt: T) -> T {
+    let two = T::new(2);
+    if dt <= T::zero() {
+        T::zero()
+    } else if t < dt {
+        let t = t / dt;
+        two * t - t * t - T::one()
+    } else if t > T::one() - dt {
+        let t = (t - T::one()) / dt;
+        t * t + two * t + T::one()
+    } else {
+        T::zero()
+    }
+}
+
+/// The residual of a band-limited change in slope of 1 per sample at phase 0, the integral of
+/// [`poly_blep`].
+fn poly_blamp<T: Real>(t: T, dt: T) -> T {
+    let sixth = T::one() / T::new(6);
+    if dt <= T::zero() {
+        T::zero()
+    } else if t < dt {
+        let t = t / dt - T::one();
+        -sixth * t * t * t
+    } else if t > T::one() - dt {
+        let t = (t - T::one()) / dt + T::one();
+        sixth * t * t * t
+    } else {
+        T::zero()
+    }
+}
+
+/// Phase oscillator whose function also gets the phase increment, for band-limiting.
+/// - Input 0: frequency in Hz.
+/// - Output 0: audio.
+#[derive(Clone)]
+pub struct BlepOsc<T, F: Clone> {
+    f: F,
+    phase: T,
+    sample_duration: T,
+    hash: u64,
+    initial_phase: Option<T>,
+}
+
+impl<T, F> BlepOsc<T, F>
+where
+    T: Real + std::fmt::Debug,
+    F: FnMut(T, T) -> T + Clone,
+{
+    pub fn with_phase(sample_rate: f64, f: F, initial_phase: Option<T>) -> Self {
+        let mut osc = Self {
+            f,
+            phase: T::zero(),
+            sample_duration: T::zero(),
+            hash: 0,
+            initial_phase,
+        };
+        osc.reset(Some(sample_rate));
+        osc
+    }
+
+    pub fn new(sample_rate: f64, f: F) -> Self {
+        Self::with_phase(sample_rate, f, None)
+    }
+}
+
+impl<T, F> AudioNode for BlepOsc<T, F>
+where
+    T: Real + std::fmt::Debug,
+    F: FnMut(T, T) -> T + Clone,
+{
+    const ID: u64 = 103; // ?
+    type Sample = T;
+    type Inputs = typenum::U1;
+    type Outputs = typenum::U1;
+    type Setting = ();
+
+    fn reset(&mut self, sample_rate: Option<f64>) {
+        self.phase = match self.initial_phase {
+            Some(p) => p,
+            None => T::zero(), // TODO: use hash
+        };
+
+        if let Some(sr) = sample_rate {
+            self.sample_duration = T::from_f64(1.0 / sr);
+        }
+    }
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let dt = input[0] * self.sample_duration;
+        self.phase += dt;
+        while self.phase > T::one() {
+            self.phase -= T::one();
+        }
+
+        [(self.f)(self.phase, dt.abs())].into()
+    }
+
+    fn process(
+        &mut self,
+        size: usize,
+        input: &[&[Self::Sample]],
+        output: &mut [&mut [Self::Sample]],
+    ) {
+        for i in 0..size {
+            output[0][i] = self.tick(&[input[0][i]].into())[0];
+        }
+    }
+
+    fn set_hash(&mut self, hash: u64) {
+        self.hash = hash;
+        self.reset(None);
+    }
+
+    fn route(&mut self, _input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        let mut output = new_signal_frame(self.outputs());
+        output[0] = Signal::Latency(0.0);
+        output
+    }
+}
+
+/// Band-limited square oscillator.
+/// - Input 0: frequency in Hz.
+/// - Input 1: duty cycle.
+/// - Output 0: square wave.
+#[derive(Default, Clone)]
+pub struct BlepSquare<T: Real> {
+    phase: T,
+    sample_duration: T,
+    hash: u64,
+    initial_phase: Option<T>,
+}
+
+impl<T> BlepSquare<T>
+where
+    T: Real + std::fmt::Debug,
+{
+    pub fn new(sample_rate: f64) -> Self {
+        let mut sq = BlepSquare::default();
+        sq.reset(Some(sample_rate));
+        sq
+    }
+
+    pub fn with_phase(sample_rate: f64, initial_phase: Option<T>) -> Self {
+        let mut sq = Self {
+            initial_phase,
+            ..Default::default()
+        };
+        sq.reset(Some(sample_rate));
+        sq
+    }
+}
+
+impl<T> AudioNode for BlepSquare<T>
+where
+    T: Real + std::fmt::Debug,
+{
+    const ID: u64 = 102; // ?
+    type Sample = T;
+    type Inputs = typenum::U2;
+    type Outputs = typenum::U1;
+    type Setting = ();
+
+    fn reset(&mut self, sample_rate: Option<f64>) {
+        self.phase = match self.initial_phase {
+            Some(p) => p,
+            None => T::zero(), // TODO: use hash
+        };
+
+        if let Some(sr) = sample_rate {
+            self.sample_duration = T::from_f64(1.0 / sr);
+        }
+    }
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let dt = input[0] * self.sample_duration;
+        self.phase += dt;
+        while self.phase > T::one() {
+            self.phase -= T::one();
+        }
+
+        let duty = input[1];
+        let naive = if self.phase < duty {
+            T::one()
+        } else {
+            -T::one()
+        };
+        // Up at phase 0, down at the duty cycle.
+        let dt = dt.abs();
+        let down = self.phase - duty;
+        let down = if down < T::zero() {
+            down + T::one()
+        } else {
+            down
+        };
+        [naive + poly_blep(self.phase, dt) - poly_blep(down, dt)].into()
+    }
+
+    fn process(
+        &mut self,
+        size: usize,
+        input: &[&[Self::Sample]],
+        output: &mut [&mut [Self::Sample]],
+    ) {
+        for i in 0..size {
+            output[0][i] = self.tick(&[input[0][i], input[1][i]].into())[0];
+        }
+    }
+
+    fn set_hash(&mut self, hash: u64) {
+        self.hash = hash;
+        self.reset(None);
+    }
+
+    fn route(&mut self, _input: &SignalFrame, _frequency: f64) -> SignalFrame {
+        let mut output = new_signal_frame(self.outputs());
+        output[0] = Signal::Latency(0.0);
+        output
+    }
+}
+
 pub fn harmonic<A>(input: A, n: u32, falloff: f32) -> Net32
 where
     A: AudioUnit32 + Clone + 'static,
@@ -346,6 +624,17 @@ impl<T: Float> AudioNode for Noise<T> {
         [value].into()
     }
 
+    fn process(
+        &mut self,
+        size: usize,
+        input: &[&[Self::Sample]],
+        output: &mut [&mut [Self::Sample]],
+    ) {
+        for i in 0..size {
+            output[0][i] = self.tick(&[input[0][i]].into())[0];
+        }
+    }
+
     #[inline]
     fn set_hash(&mut self, hash: u64) {
         self.hash = hash;
@@ -362,9 +651,10 @@ impl<T: Float> AudioNode for Noise<T> {
 #[cfg(test)]
 mod tests {
     use crate::*;
+    use fundsp::hacker32::{An, AudioUnit32, Wave32, DEFAULT_SR};
 
     #[test]
-    fn harmonic() {
+    fn harmonic() -> Result<(), Error> {
         let asyn = Asyn {
             pitch: Pitch {
                 frequency: 110.0,
@@ -383,6 +673,84 @@ mod tests {
             ..Default::default()
         };
 
-        asyn.to_wav().save_wav16("harmonic.wav").unwrap();
+        asyn.save_wav("harmonic.wav", SampleFormat::I16)
+    }
+
+    #[test]
+    fn noise() {
+        for waveform in [Waveform::White, Waveform::Pink, Waveform::Brown] {
+            for interpolate_noise in [false, true] {
+                let wave = Asyn {
+                    tone: Tone {
+                        waveform,
+                        interpolate_noise,
+                        ..Default::default()
+                    },
+                    amplitude: Amplitude {
+                        sustain: 0.1,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+                .to_wav();
+                assert!(wave.amplitude() > 0.0, "{waveform:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn band_limited() {
+        // The level of `frequency`, by the Goertzel algorithm.
+        let level = |wave: &Wave32, frequency: f32| {
+            let w = std::f32::consts::TAU * frequency / wave.sample_rate() as f32;
+            let (mut s1, mut s2) = (0.0f32, 0.0f32);
+            for x in wave.channel(0) {
+                (s2, s1) = (s1, x + 2.0 * w.cos() * s1 - s2);
+            }
+            (s1 * s1 + s2 * s2 - 2.0 * w.cos() * s1 * s2).sqrt() / wave.len() as f32
+        };
+
+        for waveform in [Waveform::Square, Waveform::Saw, Waveform::Triangle] {
+            let render = |band_limited| {
+                Asyn {
+                    pitch: Pitch {
+                        frequency: 5000.0,
+                        ..Default::default()
+                    },
+                    tone: Tone {
+                        waveform,
+                        band_limited,
+                        ..Default::default()
+                    },
+                    amplitude: Amplitude {
+                        sustain: 0.5,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }
+                .to_wav()
+            };
+            let (naive, blep) = (render(false), render(true));
+
+            // The fundamental is kept. The 9th harmonic (45 kHz) folds back to 900 Hz.
+            assert!((level(&blep, 5000.0) / level(&naive, 5000.0) - 1.0).abs() < 0.1);
+            assert!(
+                level(&blep, 900.0) < 0.1 * level(&naive, 900.0),
+                "{waveform:?}"
+            );
+        }
+
+        // They start at the same phase as the naive oscillators.
+        let start = |unit: &mut dyn AudioUnit32| {
+            unit.reset(Some(44_100.0));
+            let mut out = [0.0];
+            unit.tick(&[100.0, 0.5], &mut out);
+            out[0]
+        };
+        for phase in [None, Some(0.25), Some(0.75)] {
+            let naive = start(&mut An(osc::Square::with_phase(DEFAULT_SR, phase)));
+            let blep = start(&mut An(osc::BlepSquare::with_phase(DEFAULT_SR, phase)));
+            assert!((naive - blep).abs() < 0.1, "{phase:?}");
+        }
     }
 }
@@ -0,0 +1,143 @@
+//! Oversampled rendering.
+//!
+//! The oscillators are naive, so harmonics above the Nyquist frequency fold back down as
+//! inharmonic tones. Rendering at a multiple of the sample rate puts the Nyquist frequency above
+//! most of them. [`decimate`] then removes everything above the target Nyquist frequency with a
+//! windowed-sinc low-pass before dropping samples.
+
+use std::f64::consts::PI;
+
+use fundsp::hacker32::Wave32;
+
+/// Filter length on each side of the center, in output samples.
+const HALF_TAPS: usize = 32;
+
+/// The passband edge, as a fraction of the output Nyquist frequency.
+const CUTOFF: f64 = 0.9;
+
+/// The low-pass kernel for decimating by `factor`: a Blackman-windowed sinc with unity gain at
+/// DC. It's symmetric, with `HALF_TAPS * factor` taps on each side of the center.
+pub(crate) fn kernel(factor: usize) -> Vec<f32> {
+    let half = (HALF_TAPS * factor) as isize;
+    // In cycles per input sample.
+    let fc = 0.5 * CUTOFF / factor as f64;
+    let taps: Vec<f64> = (-half..=half)
+        .map(|k| {
+            let sinc = match k {
+                0 => 2.0 * fc,
+                _ => (2.0 * PI * fc * k as f64).sin() / (PI * k as f64),
+            };
+            let x = PI * (k + half) as f64 / half as f64;
+            sinc * (0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos())
+        })
+        .collect();
+    let sum: f64 = taps.iter().sum();
+    taps.iter().map(|t| (t / sum) as f32).collect()
+}
+
+/// Low-pass `wave` and keep every `factor`th sample. The filter is centered on each kept sample,
+/// so nothing is delayed, and the wave is taken as silent past its ends. The result has
+/// `len / factor` samples (rounded up) at `sample_rate / factor`.
+pub fn decimate(wave: &Wave32, factor: usize) -> Wave32 {
+    assert!(factor > 0, "decimation factor must be positive");
+    let mut output = Wave32::new(0, wave.sample_rate() / factor as f64);
+    if factor == 1 {
+        for channel in 0..wave.channels() {
+            output.push_channel(wave.channel(channel));
+        }
+        return output;
+    }
+
+    let kernel = kernel(factor);
+    let half = kernel.len() / 2;
+    for channel in 0..wave.channels() {
+        let input = wave.channel(channel);
+        let samples: Vec<f32> = (0..input.len())
+            .step_by(factor)
+            .map(|center| {
+                // Clip the kernel to the input.
+                let start = center.saturating_sub(half);
+                let end = (center + half + 1).min(input.len());
+                let taps = &kernel[start + half - center..end + half - center];
+                input[start..end]
+                    .iter()
+                    .zip(taps)
+                    .map(|(x, t)| (x * t) as f64)
+                    .sum::<f64>() as f32
+            })
+            .collect();
+        output.push_channel(&samples);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Amplitude, Asyn, Pitch, RenderOptions, Tone, Waveform};
+
+    /// The level of `frequency` in `wave`, by the Goertzel algorithm.
+    fn level(wave: &Wave32, frequency: f64) -> f64 {
+        let w = 2.0 * PI * frequency / wave.sample_rate();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for x in wave.channel(0) {
+            let s = *x as f64 + 2.0 * w.cos() * s1 - s2;
+            (s2, s1) = (s1, s);
+        }
+        (s1 * s1 + s2 * s2 - 2.0 * w.cos() * s1 * s2).sqrt() / wave.len() as f64
+    }
+
+    #[test]
+    fn less_aliasing() {
+        // A 5 kHz square has harmonics at 25, 35 and 45 kHz that fold back to 19.1, 9.1 and
+        // 0.9 kHz.
+        let asyn = Asyn {
+            pitch: Pitch {
+                frequency: 5000.0,
+                ..Default::default()
+            },
+            tone: Tone::from(Waveform::Square),
+            amplitude: Amplitude {
+                sustain: 0.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let render = |oversample| {
+            asyn.clone().render(&RenderOptions {
+                oversample,
+                ..Default::default()
+            })
+        };
+
+        let naive = render(1);
+        assert!(naive
+            .channel(0)
+            .iter()
+            .zip(asyn.clone().to_wav().channel(0))
+            .all(|(a, b)| a.to_bits() == b.to_bits()));
+
+        let oversampled = render(8);
+        assert_eq!(oversampled.len(), naive.len());
+        assert_eq!(oversampled.sample_rate(), naive.sample_rate());
+        // The fundamental is kept.
+        let fundamental = level(&oversampled, 5000.0);
+        assert!((fundamental / level(&naive, 5000.0) - 1.0).abs() < 0.1);
+        for alias in [900.0, 9100.0] {
+            assert!(level(&naive, alias) > 0.02 * fundamental);
+            assert!(level(&oversampled, alias) < 0.2 * level(&naive, alias));
+        }
+    }
+
+    #[test]
+    fn passband() {
+        // A quiet low sine passes through unchanged, away from the ends.
+        let wave = Wave32::render(44_100.0 * 4.0, 0.1, &mut fundsp::hacker32::sine_hz(440.0));
+        let decimated = decimate(&wave, 4);
+        assert_eq!(decimated.len(), wave.len().div_ceil(4));
+        assert_eq!(decimated.sample_rate(), 44_100.0);
+        for i in HALF_TAPS..decimated.len() - HALF_TAPS {
+            assert!((decimated.at(0, i) - wave.at(0, i * 4)).abs() < 1e-3);
+        }
+    }
+}
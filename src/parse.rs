@@ -0,0 +1,484 @@
+//! Parsing of the [`Display`](std::fmt::Display) format, e.g.
+//! `[500hz sweep: 100] [tone: Square duty: 0.25 sweep: 0.00] [amplitude: 0.10 sustain]`.
+//!
+//! Fields that aren't printed take their defaults, so the plain format parses to the rounded
+//! values it shows. The alternate format (`{:#}`) prints everything and parses back exactly.
+
+use std::{error, fmt, str::FromStr};
+
+use crate::{
+    note::{Mode, Scale},
+    types::{Amplitude, Asyn, Filters, Pitch, Stereo, Tone, Waveform},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub expected: &'static str,
+    /// The start of the remaining input.
+    pub found: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, found {:?}", self.expected, self.found)
+    }
+}
+
+impl error::Error for ParseError {}
+
+struct Cursor<'a>(&'a str);
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, s: &str) -> bool {
+        match self.0.strip_prefix(s) {
+            Some(rest) => {
+                self.0 = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, s: &'static str) -> Result<(), ParseError> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error(s))
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let len = self.0.find(|c| !f(c)).unwrap_or(self.0.len());
+        let (token, rest) = self.0.split_at(len);
+        self.0 = rest;
+        token
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, ParseError> {
+        let start = self.0;
+        let token = match ["NaN", "inf", "-inf"].into_iter().find(|s| self.eat(s)) {
+            Some(s) => s,
+            None => self.take_while(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.')),
+        };
+        token.parse().map_err(|_| {
+            self.0 = start;
+            self.error("a number")
+        })
+    }
+
+    /// A note name without an octave, as a pitch class from C = 0, so Cb is -1.
+    fn pitch_class(&mut self) -> Result<i32, ParseError> {
+        let Some(i) = ["C", "D", "E", "F", "G", "A", "B"]
+            .into_iter()
+            .position(|n| self.eat(n))
+        else {
+            return Err(self.error("a note"));
+        };
+        let mut class = [0, 2, 4, 5, 7, 9, 11][i];
+        loop {
+            if self.eat("#") {
+                class += 1;
+            } else if self.eat("b") {
+                class -= 1;
+            } else {
+                break;
+            }
+        }
+        Ok(class)
+    }
+
+    /// A note name and octave, as a MIDI note number.
+    fn note(&mut self) -> Result<f32, ParseError> {
+        let start = self.0;
+        let class = self.pitch_class()?;
+        let octave: i32 = self.number().map_err(|_| {
+            self.0 = start;
+            self.error("a note")
+        })?;
+        Ok((12 * (octave + 1) + class) as f32)
+    }
+
+    /// The contents of the next `[...]` section.
+    fn section(&mut self) -> Result<&'a str, ParseError> {
+        self.expect("[")?;
+        let end = self.0.find(']').ok_or_else(|| self.error("]"))?;
+        let section = &self.0[..end];
+        self.0 = &self.0[end + 1..];
+        Ok(section)
+    }
+
+    fn end(&self) -> Result<(), ParseError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(self.error("end of input"))
+        }
+    }
+
+    fn error(&self, expected: &'static str) -> ParseError {
+        ParseError {
+            expected,
+            found: self.0.chars().take(20).collect(),
+        }
+    }
+}
+
+/// Parse a note name such as `C4`, `F#3` or `Bb-1` as a MIDI note number.
+pub(crate) fn note(s: &str) -> Result<f32, ParseError> {
+    let mut c = Cursor(s);
+    let note = c.note()?;
+    c.end()?;
+    Ok(note)
+}
+
+impl FromStr for Scale {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut c = Cursor(s);
+        let key = c.pitch_class()?.rem_euclid(12) as u8;
+        c.expect(" ")?;
+        let mode = Mode::NAMES
+            .iter()
+            .find(|(name, _)| c.eat(name))
+            .map(|(_, mode)| *mode)
+            .ok_or_else(|| c.error("a mode"))?;
+        c.end()?;
+        Ok(Scale::new(key, mode))
+    }
+}
+
+impl FromStr for Asyn {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut c = Cursor(s);
+
+        let pitch = c.section()?.parse()?;
+        c.expect(" ")?;
+        let tone = c.section()?.parse()?;
+        c.expect(" ")?;
+        let amplitude = c.section()?.parse()?;
+        let filters = if c.0.starts_with('[') && !c.0.starts_with("[stereo:") {
+            Some(c.section()?.parse()?)
+        } else {
+            None
+        };
+        let stereo = if c.0.starts_with("[stereo:") {
+            Some(c.section()?.parse()?)
+        } else {
+            None
+        };
+
+        let mut asyn = Asyn {
+            pitch,
+            tone,
+            amplitude,
+            filters,
+            stereo,
+            ..Default::default()
+        };
+
+        if c.eat(" ") {
+            let mut s = Cursor(c.section()?);
+            s.expect("seed: ")?;
+            asyn.seed = s.number()?;
+            s.expect(" mutations: ")?;
+            asyn.mutations = s.number()?;
+            s.end()?;
+        }
+
+        c.end()?;
+        Ok(asyn)
+    }
+}
+
+impl FromStr for Pitch {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut c = Cursor(s);
+        // A frequency, or a note name.
+        let frequency = match c.number() {
+            Ok(frequency) => {
+                c.expect("hz")?;
+                frequency
+            }
+            Err(_) => c
+                .note()
+                .map(crate::note::midi_to_hz)
+                .map_err(|_| c.error("a frequency or note"))?,
+        };
+        let mut pitch = Pitch {
+            frequency,
+            ..Default::default()
+        };
+        let mut legacy = false;
+
+        let pair = |c: &mut Cursor| -> Result<(f32, f32), ParseError> {
+            let a = c.number()?;
+            c.expect(", ")?;
+            let b = c.number()?;
+            c.expect(")")?;
+            Ok((a, b))
+        };
+
+        loop {
+            if c.eat(" sweep: ") {
+                pitch.frequency_sweep = c.number()?;
+            } else if c.eat(" delta sweep: ") {
+                pitch.frequency_delta_sweep = c.number()?;
+            } else if c.eat(" vibrato: (") {
+                (pitch.vibrato_depth, pitch.vibrato_frequency) = pair(&mut c)?;
+            } else if c.eat(" repeat: ") {
+                pitch.repeat_frequency = c.number()?;
+            } else if c.eat(" jumps:") {
+                while c.eat(" (") {
+                    pitch.frequency_jumps.push(pair(&mut c)?);
+                }
+            } else if c.eat(" jump1: (") || c.eat(" jump2: (") {
+                // The two jumps of older versions, in any order.
+                let jump = pair(&mut c)?;
+                if jump.1 != 0.0 {
+                    pitch.frequency_jumps.push(jump);
+                }
+                legacy = true;
+            } else {
+                break;
+            }
+        }
+
+        c.end()?;
+        if legacy {
+            let jumps = std::mem::take(&mut pitch.frequency_jumps);
+            pitch = pitch.with_jumps(jumps);
+        }
+        if pitch.check_jumps().is_err() {
+            return Err(Cursor(s).error("jumps in order of onset, with amounts above -1"));
+        }
+        Ok(pitch)
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Waveform::*;
+
+        [
+            Sine, Triangle, Saw, Square, Tangent, Whistle, Breaker, White, Pink, Brown,
+        ]
+        .into_iter()
+        .find(|w| format!("{w:?}") == s)
+        .ok_or_else(|| Cursor(s).error("a waveform"))
+    }
+}
+
+impl FromStr for Tone {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut c = Cursor(s);
+        c.expect("tone: ")?;
+        let mut tone = Tone {
+            waveform: c.take_while(|c| c.is_ascii_alphabetic()).parse()?,
+            // Only printed if set.
+            interpolate_noise: false,
+            ..Default::default()
+        };
+
+        loop {
+            if c.eat(" interp") {
+                tone.interpolate_noise = true;
+            } else if c.eat(" band_limited") {
+                tone.band_limited = true;
+            } else if c.eat(" duty: ") {
+                tone.square_duty = c.number()?;
+                c.expect(" sweep: ")?;
+                tone.square_duty_sweep = c.number()?;
+            } else if c.eat(" harmonics: ") {
+                tone.harmonics = c.number()?;
+                c.expect(" falloff: ")?;
+                tone.harmonics_falloff = c.number()?;
+            } else {
+                break;
+            }
+        }
+
+        c.end()?;
+        Ok(tone)
+    }
+}
+
+impl FromStr for Amplitude {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut c = Cursor(s);
+        c.expect("amplitude:")?;
+        let mut amplitude = Amplitude::default();
+
+        loop {
+            if c.eat(" tremolo: ") {
+                amplitude.tremolo_depth = c.number()?;
+                c.expect("/")?;
+                amplitude.tremolo_frequency = c.number()?;
+            } else if c.eat(" ") {
+                let v = c.number()?;
+                if c.eat(" attack") {
+                    amplitude.attack = v;
+                } else if c.eat(" sustain") {
+                    amplitude.sustain = v;
+                } else if c.eat(" punch") {
+                    amplitude.punch = v;
+                } else if c.eat(" decay") {
+                    amplitude.decay = v;
+                } else {
+                    return Err(c.error("attack, sustain, punch or decay"));
+                }
+            } else {
+                break;
+            }
+        }
+
+        c.end()?;
+        Ok(amplitude)
+    }
+}
+
+impl FromStr for Filters {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn pair<T: FromStr>(c: &mut Cursor) -> Result<(T, T), ParseError> {
+            let a = c.number()?;
+            c.expect("/")?;
+            Ok((a, c.number()?))
+        }
+
+        let mut c = Cursor(s);
+        let mut filters = Filters::default();
+
+        loop {
+            if c.eat(" flanger: ") {
+                (filters.flanger_offset, filters.flanger_offset_sweep) = pair(&mut c)?;
+            } else if c.eat(" bit_crush: ") {
+                (filters.bit_crush, filters.bit_crush_sweep) = pair(&mut c)?;
+            } else if c.eat(" low_pass: ") {
+                (filters.low_pass_cutoff, filters.low_pass_sweep) = pair(&mut c)?;
+            } else if c.eat(" high_pass: ") {
+                (filters.high_pass_cutoff, filters.high_pass_sweep) = pair(&mut c)?;
+            } else if c.eat(" compression: ") {
+                filters.compression = c.number()?;
+            } else if c.eat(" normalization: true") {
+                filters.normalization = true;
+            } else if c.eat(" normalization: false") {
+                filters.normalization = false;
+            } else if c.eat(" amplification: ") {
+                filters.amplification = c.number()?;
+            } else {
+                break;
+            }
+        }
+
+        c.end()?;
+        Ok(filters)
+    }
+}
+
+impl FromStr for Stereo {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut c = Cursor(s);
+        c.expect("stereo:")?;
+        let mut stereo = Stereo::default();
+
+        loop {
+            if c.eat(" pan: ") {
+                stereo.pan = c.number()?;
+            } else if c.eat(" sweep: ") {
+                stereo.pan_sweep = c.number()?;
+            } else if c.eat(" auto_pan: ") {
+                stereo.auto_pan_depth = c.number()?;
+                c.expect("/")?;
+                stereo.auto_pan_frequency = c.number()?;
+            } else if c.eat(" width: ") {
+                stereo.width = c.number()?;
+            } else {
+                break;
+            }
+        }
+
+        c.end()?;
+        Ok(stereo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::presets::*;
+
+    #[test]
+    fn lossless_round_trip() {
+        let rng = &mut funutd::Rnd::from_u64(1);
+        for preset in [blip, explosion, hit, jump, laser, pickup, powerup, random] {
+            for _ in 0..10 {
+                let asyn = preset(rng).mutate(rng);
+                assert_eq!(format!("{asyn:#}").parse::<Asyn>().unwrap(), asyn);
+            }
+        }
+
+        let asyn = Asyn {
+            tone: Tone {
+                band_limited: true,
+                ..Default::default()
+            },
+            stereo: Some(Stereo::default().mutate(rng)),
+            pitch: Pitch::default().with_jumps([(0.1, 0.25), (0.3, 0.5), (0.5, -0.25)]),
+            ..Default::default()
+        };
+        assert_eq!(format!("{asyn:#}").parse::<Asyn>().unwrap(), asyn);
+    }
+
+    #[test]
+    fn parse_display() {
+        let s = "[500hz sweep: 100] [tone: Square duty: 0.25 sweep: -0.10] [amplitude: 0.10 sustain 0.20 decay][ low_pass: 3000/-200]";
+        let asyn: Asyn = s.parse().unwrap();
+        assert_eq!(asyn.pitch.frequency_sweep, 100.0);
+        assert_eq!(asyn.tone.square_duty_sweep, -0.1);
+        assert_eq!(asyn.amplitude.decay, 0.2);
+        assert_eq!(asyn.filters.as_ref().unwrap().low_pass_cutoff, 3000.0);
+        assert_eq!(asyn.to_string(), s);
+
+        let s =
+            "[500hz] [tone: Sine] [amplitude: 0.10 sustain][stereo: pan: -0.50 auto_pan: 0.25/2.0]";
+        let asyn: Asyn = s.parse().unwrap();
+        assert_eq!(asyn.filters, None);
+        assert_eq!(asyn.stereo.unwrap().auto_pan_frequency, 2.0);
+        assert_eq!(asyn.to_string(), s);
+
+        assert_eq!(
+            "[500hz] [tone: Kazoo] [amplitude:]".parse::<Asyn>(),
+            Err(ParseError {
+                expected: "a waveform",
+                found: "Kazoo".into()
+            })
+        );
+
+        // Older versions had two jumps, in any order.
+        let pitch: Pitch = "500hz jump1: (0.66, 0.50) jump2: (0.33, 0.25)"
+            .parse()
+            .unwrap();
+        assert_eq!(pitch.frequency_jumps, [(0.33, 0.25), (0.66, 0.5)]);
+        assert_eq!(
+            "500hz jumps: (0.66, 0.50) (0.33, 0.25)".parse::<Pitch>(),
+            Err(ParseError {
+                expected: "jumps in order of onset, with amounts above -1",
+                found: "500hz jumps: (0.66, ".into()
+            })
+        );
+    }
+}
@@ -9,7 +9,7 @@ pub fn explosion(rng: &mut funutd::Rnd) -> Asyn {
 
     let mut amplitude = Amplitude {
         sustain: rng.f32_in(0.05, 0.1),
-        punch: rng.bool(0.5).then(|| rng.f32()).unwrap_or_default(),
+        punch: if rng.bool(0.5) { rng.f32() } else { 0.0 },
         decay: rng.f32_in(0.3, 0.5),
         ..Default::default()
     };
@@ -26,7 +26,7 @@ pub fn jump(rng: &mut funutd::Rnd) -> Asyn {
         amplitude: Amplitude {
             sustain: rng.f32_in(0.02, 0.1),
             decay: rng.f32_in(0.05, 0.4),
-            punch: rng.bool(0.5).then(|| rng.f32()).unwrap_or_default(),
+            punch: if rng.bool(0.5) { rng.f32() } else { 0.0 },
             ..Default::default()
         },
 
@@ -11,7 +11,7 @@ pub fn powerup(rng: &mut funutd::Rnd) -> Asyn {
         ),
         amplitude: Amplitude {
             sustain: rng.f32_in(0.05, 0.2),
-            punch: rng.bool(0.5).then(|| rng.f32()).unwrap_or_default(),
+            punch: if rng.bool(0.5) { rng.f32() } else { 0.0 },
             decay: rng.f32_in(0.1, 0.4),
             ..Default::default()
         },
@@ -19,10 +19,11 @@ pub fn powerup(rng: &mut funutd::Rnd) -> Asyn {
             frequency: rng.f32_in(500.0, 2_000.0),
             frequency_sweep: rng.f32_in(0.0, 2_000.0),
             frequency_delta_sweep: rng.f32_in(0.0, 2_000.0),
-            repeat_frequency: rng
-                .bool(0.5)
-                .then(|| rng.f32_in(0.0, 20.0))
-                .unwrap_or_default(),
+            repeat_frequency: if rng.bool(0.5) {
+                rng.f32_in(0.0, 20.0)
+            } else {
+                0.0
+            },
             ..Default::default()
         },
         ..Default::default()
@@ -71,7 +71,7 @@ pub fn random(rng: &mut funutd::Rnd) -> Asyn {
         f.compression = rng.f32_in(0.5, 2.0);
     }
 
-    // TODO: normalization/amplification
+    // Normalization and amplification keep their defaults, as in jfxr.
 
     let mut pitch = Pitch {
         frequency: rng.f32_in(10.0, 10_000.0),
@@ -95,13 +95,12 @@ pub fn random(rng: &mut funutd::Rnd) -> Asyn {
     }
 
     if repeat >= 2 {
-        pitch.frequency_jump1 = (rng.f32(), rng.f32_in(-100.0, 100.0));
+        // Down to -1 would leave no frequency.
+        let mut jumps = vec![(rng.f32(), rng.f32_in(-0.95, 1.0))];
         if rng.bool(0.5) {
-            pitch.frequency_jump1 = (rng.f32(), rng.f32_in(-100.0, 100.0));
-            if pitch.frequency_jump1.0 < pitch.frequency_jump2.0 {
-                std::mem::swap(&mut pitch.frequency_jump1.0, &mut pitch.frequency_jump2.0);
-            }
+            jumps.push((rng.f32(), rng.f32_in(-0.95, 1.0)));
         }
+        pitch = pitch.with_jumps(jumps);
     }
 
     if rng.bool(0.5) {
@@ -0,0 +1,188 @@
+//! Named export profiles for target platforms.
+//!
+//! A profile renders at its sample rate, oversampled so the result is band-limited (see
+//! [`RenderOptions::oversample`]), mixes down to mono if the target is mono, then dithers to its
+//! sample format and writes its container.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use fundsp::hacker32::Wave32;
+
+use crate::{
+    error::Error,
+    export::{self, Dither, Endian, SampleFormat},
+    types::{Asyn, RenderOptions},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Container {
+    Wav,
+    /// Headerless, little-endian.
+    Pcm,
+}
+
+impl Container {
+    /// The file extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Container::Wav => "wav",
+            Container::Pcm => "pcm",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ExportProfile {
+    pub name: &'static str,
+    pub sample_rate: f64,
+    pub format: SampleFormat,
+    /// Mix stereo sounds down to one channel.
+    pub mono: bool,
+    pub dither: Dither,
+    pub oversample: usize,
+    pub container: Container,
+}
+
+/// Every profile, by name.
+pub const PROFILES: [ExportProfile; 5] = [
+    ExportProfile {
+        name: "retro-8bit-8k",
+        sample_rate: 8_000.0,
+        format: SampleFormat::U8,
+        mono: true,
+        dither: Dither::Tpdf,
+        oversample: 8,
+        container: Container::Wav,
+    },
+    ExportProfile {
+        name: "retro-8bit-11k",
+        sample_rate: 11_025.0,
+        format: SampleFormat::U8,
+        mono: true,
+        dither: Dither::Tpdf,
+        oversample: 8,
+        container: Container::Wav,
+    },
+    // The GBA's DirectSound plays signed 8-bit samples, usually at 16384 Hz.
+    ExportProfile {
+        name: "gba-16k",
+        sample_rate: 16_384.0,
+        format: SampleFormat::I8,
+        mono: true,
+        dither: Dither::Tpdf,
+        oversample: 4,
+        container: Container::Pcm,
+    },
+    ExportProfile {
+        name: "modern-44k",
+        sample_rate: 44_100.0,
+        format: SampleFormat::I16,
+        mono: false,
+        dither: Dither::Tpdf,
+        oversample: 2,
+        container: Container::Wav,
+    },
+    ExportProfile {
+        name: "modern-48k-float",
+        sample_rate: 48_000.0,
+        format: SampleFormat::F32,
+        mono: false,
+        dither: Dither::None,
+        oversample: 2,
+        container: Container::Wav,
+    },
+];
+
+/// Look up a profile by name.
+pub fn profile(name: &str) -> Option<ExportProfile> {
+    PROFILES.iter().find(|p| p.name == name).copied()
+}
+
+/// The mean of the channels.
+fn mix_down(wave: &Wave32) -> Wave32 {
+    let scale = 1.0 / wave.channels() as f32;
+    let samples: Vec<f32> = (0..wave.len())
+        .map(|i| (0..wave.channels()).map(|c| wave.at(c, i)).sum::<f32>() * scale)
+        .collect();
+    Wave32::from_samples(wave.sample_rate(), &samples)
+}
+
+impl ExportProfile {
+    /// Render `asyn` to the profile's rate, channels and sample steps.
+    pub fn render(&self, asyn: &Asyn) -> Wave32 {
+        let mut wave = asyn.clone().render(&RenderOptions {
+            sample_rate: self.sample_rate,
+            oversample: self.oversample,
+            ..Default::default()
+        });
+        if self.mono && wave.channels() > 1 {
+            wave = mix_down(&wave);
+        }
+        export::dither(&wave, self.format, self.dither, asyn.seed)
+    }
+
+    /// Render and write `asyn` in the profile's container.
+    pub fn write(&self, asyn: &Asyn, writer: impl Write) -> Result<(), Error> {
+        let wave = self.render(asyn);
+        match self.container {
+            Container::Wav => export::write_wav(&wave, self.format, writer),
+            Container::Pcm => export::write_pcm(&wave, self.format, Endian::Little, writer),
+        }
+    }
+
+    /// Render and save `asyn` in the profile's container.
+    pub fn save(&self, asyn: &Asyn, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(asyn, &mut writer)?;
+        Ok(writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{presets::*, types::Stereo};
+
+    #[test]
+    fn profiles() {
+        let rng = &mut funutd::Rnd::from_u64(23);
+        let asyn = Asyn {
+            stereo: Some(Stereo {
+                pan: 0.5,
+                ..Default::default()
+            }),
+            ..laser(rng)
+        };
+
+        for profile in PROFILES {
+            let wave = profile.render(&asyn);
+            assert_eq!(wave.sample_rate(), profile.sample_rate);
+            assert_eq!(
+                wave.len(),
+                (asyn.len() as f64 * profile.sample_rate).round() as usize
+            );
+            assert_eq!(wave.channels(), if profile.mono { 1 } else { 2 });
+
+            let mut bytes = Vec::new();
+            profile.write(&asyn, &mut bytes).unwrap();
+            let data = wave.len() * wave.channels() * profile.format.bytes();
+            match profile.container {
+                Container::Wav => assert!(bytes.starts_with(b"RIFF") && bytes.len() > data),
+                Container::Pcm => assert_eq!(bytes.len(), data),
+            }
+        }
+
+        let retro = profile("retro-8bit-11k").unwrap();
+        let wave = retro.render(&asyn);
+        // Every sample is on an 8-bit step.
+        assert!(wave
+            .channel(0)
+            .iter()
+            .all(|x| (x * 127.0 - (x * 127.0).round()).abs() < 1e-4));
+        assert_eq!(profile("retro-8bit-44k"), None);
+    }
+}
@@ -0,0 +1,395 @@
+//! QOA ("Quite OK Audio") export and import.
+//!
+//! Follows the reference encoder: 16-bit samples, frames of 256 slices of 20 samples per
+//! channel, each slice quantized to 3 bits per sample against a four-tap LMS predictor. See
+//! <https://qoaformat.org/qoa-specification.pdf>.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::Path,
+};
+
+use fundsp::hacker32::Wave32;
+
+use crate::{error::Error, export::SampleFormat, types::Asyn};
+
+const MAGIC: &[u8; 4] = b"qoaf";
+const SLICE_LEN: usize = 20;
+const SLICES_PER_FRAME: usize = 256;
+const FRAME_LEN: usize = SLICE_LEN * SLICES_PER_FRAME;
+
+const SCALEFACTORS: [i32; 16] = [
+    1, 7, 21, 45, 84, 138, 211, 304, 421, 562, 731, 928, 1157, 1419, 1715, 2048,
+];
+
+/// Quantized residual for each clamped `residual / scalefactor` in -8..=8.
+const QUANT: [u8; 17] = [7, 7, 7, 5, 5, 3, 3, 1, 0, 0, 2, 2, 4, 4, 6, 6, 6];
+
+const DEQUANT: [f32; 8] = [0.75, -0.75, 2.5, -2.5, 4.5, -4.5, 7.0, -7.0];
+
+fn dequantize(scalefactor: usize, quantized: u8) -> i32 {
+    (SCALEFACTORS[scalefactor] as f32 * DEQUANT[quantized as usize]).round() as i32
+}
+
+/// `v / SCALEFACTORS[scalefactor]`, rounded away from zero, with the reference's fixed point
+/// reciprocal so files match it bit for bit.
+fn divide(v: i32, scalefactor: usize) -> i32 {
+    let sf = SCALEFACTORS[scalefactor];
+    let reciprocal = ((1 << 16) + sf - 1) / sf;
+    let n = ((v as i64 * reciprocal as i64 + (1 << 15)) >> 16) as i32;
+    n + v.signum() - n.signum()
+}
+
+#[derive(Copy, Clone)]
+struct Lms {
+    history: [i32; 4],
+    weights: [i32; 4],
+}
+
+impl Default for Lms {
+    fn default() -> Self {
+        Self {
+            history: [0; 4],
+            weights: [0, 0, -(1 << 13), 1 << 14],
+        }
+    }
+}
+
+impl Lms {
+    /// Weights can grow past 16 bits within a frame, so the products are summed in 64 bits.
+    /// Shifted down, the sum fits in 32.
+    fn predict(&self) -> i32 {
+        (self
+            .history
+            .iter()
+            .zip(self.weights)
+            .map(|(h, w)| *h as i64 * w as i64)
+            .sum::<i64>()
+            >> 13) as i32
+    }
+
+    fn update(&mut self, sample: i32, residual: i32) {
+        let delta = residual >> 4;
+        for (h, w) in self.history.iter().zip(&mut self.weights) {
+            *w += if *h < 0 { -delta } else { delta };
+        }
+        self.history.rotate_left(1);
+        self.history[3] = sample;
+    }
+
+    fn pack(values: [i32; 4]) -> u64 {
+        values
+            .iter()
+            .fold(0, |acc, v| (acc << 16) | (*v as u16 as u64))
+    }
+
+    fn unpack(v: u64) -> [i32; 4] {
+        std::array::from_fn(|i| (v >> (48 - 16 * i)) as i16 as i32)
+    }
+}
+
+fn clamp16(v: i32) -> i32 {
+    v.clamp(i16::MIN as i32, i16::MAX as i32)
+}
+
+/// Encode one slice, trying each scalefactor (starting from the last one used) and keeping the
+/// one with the least squared error.
+fn encode_slice(samples: &[i32], lms: &mut Lms, last_scalefactor: &mut usize) -> u64 {
+    let mut best = (u64::MAX, 0, *lms, 0);
+    for i in 0..16 {
+        let scalefactor = (i + *last_scalefactor) % 16;
+        let mut state = *lms;
+        let mut slice = scalefactor as u64;
+        let mut error = 0u64;
+        for sample in samples {
+            let predicted = state.predict();
+            let quantized =
+                QUANT[(divide(sample - predicted, scalefactor).clamp(-8, 8) + 8) as usize];
+            let dequantized = dequantize(scalefactor, quantized);
+            let reconstructed = clamp16(predicted + dequantized);
+
+            // Penalize large weights, which make the predictor unstable.
+            let penalty = ((state
+                .weights
+                .iter()
+                .map(|w| (*w as i64).pow(2))
+                .sum::<i64>()
+                >> 18)
+                - 0x8ff)
+                .max(0) as u64;
+            error += ((sample - reconstructed) as i64).pow(2) as u64 + penalty * penalty;
+            if error > best.0 {
+                break;
+            }
+            state.update(reconstructed, dequantized);
+            slice = (slice << 3) | quantized as u64;
+        }
+        if error < best.0 {
+            best = (error, slice, state, scalefactor);
+        }
+    }
+
+    let (_, slice, state, scalefactor) = best;
+    *lms = state;
+    *last_scalefactor = scalefactor;
+    slice << ((SLICE_LEN - samples.len()) * 3)
+}
+
+/// Write a QOA file. Samples are clipped to 16 bits.
+pub fn write_qoa(wave: &Wave32, mut writer: impl Write) -> Result<(), Error> {
+    let invalid = |parameter, reason| Error::Invalid { parameter, reason };
+
+    let sample_rate = wave.sample_rate();
+    if !((1.0..=16_777_215.0).contains(&sample_rate) && sample_rate.fract() == 0.0) {
+        return Err(invalid(
+            "sample rate",
+            "not a whole number of hertz under 2^24",
+        ));
+    }
+    let channels = wave.channels();
+    if !(1..=8).contains(&channels) {
+        return Err(invalid(
+            "channels",
+            "the reference decoder takes 1 to 8 channels",
+        ));
+    }
+    let len = u32::try_from(wave.len()).map_err(|_| invalid("length", "over 2^32 samples"))?;
+    if len == 0 {
+        // A zero length marks a streaming file.
+        return Err(invalid("length", "empty"));
+    }
+
+    let samples: Vec<Vec<i32>> = (0..channels)
+        .map(|c| {
+            (0..wave.len())
+                .map(|i| SampleFormat::I16.to_int(wave.at(c, i)))
+                .collect()
+        })
+        .collect();
+
+    let mut out = Vec::new();
+    out.extend(MAGIC);
+    out.extend(len.to_be_bytes());
+
+    let mut lms = vec![Lms::default(); channels];
+    let mut scalefactors = vec![0; channels];
+    for start in (0..wave.len()).step_by(FRAME_LEN) {
+        let n = FRAME_LEN.min(wave.len() - start);
+        let slices = n.div_ceil(SLICE_LEN);
+        let size = 8 + channels * 16 + slices * channels * 8;
+
+        out.push(channels as u8);
+        out.extend(&(sample_rate as u32).to_be_bytes()[1..]);
+        out.extend((n as u16).to_be_bytes());
+        out.extend((size as u16).to_be_bytes());
+        for state in &lms {
+            out.extend(Lms::pack(state.history).to_be_bytes());
+            out.extend(Lms::pack(state.weights).to_be_bytes());
+        }
+
+        // Slices are interleaved by channel.
+        for slice in (start..start + n).step_by(SLICE_LEN) {
+            let end = (slice + SLICE_LEN).min(start + n);
+            for c in 0..channels {
+                let v = encode_slice(&samples[c][slice..end], &mut lms[c], &mut scalefactors[c]);
+                out.extend(v.to_be_bytes());
+            }
+        }
+    }
+
+    Ok(writer.write_all(&out)?)
+}
+
+/// Read a QOA file written by [`write_qoa`] or another encoder. Streaming files (with no length
+/// in the header) are not supported.
+pub fn read_qoa(mut reader: impl Read) -> Result<Wave32, Error> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let mut rest = &data[..];
+    let mut take = |n: usize| -> Result<&[u8], Error> {
+        let (bytes, tail) = rest
+            .split_at_checked(n)
+            .ok_or(Error::Format("truncated QOA file"))?;
+        rest = tail;
+        Ok(bytes)
+    };
+    let u64_be = |bytes: &[u8]| u64::from_be_bytes(bytes.try_into().unwrap());
+
+    if take(4)? != MAGIC {
+        return Err(Error::Format("not a QOA file"));
+    }
+    let len = u32::from_be_bytes(take(4)?.try_into().unwrap()) as usize;
+    if len == 0 {
+        return Err(Error::Format("streaming QOA files are not supported"));
+    }
+
+    let mut wave: Option<Wave32> = None;
+    let mut lms = Vec::new();
+    let mut start = 0;
+    while start < len {
+        let header = take(8)?;
+        let channels = header[0] as usize;
+        let sample_rate = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        let n = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if channels == 0 || sample_rate == 0 || n == 0 || n > FRAME_LEN || start + n > len {
+            return Err(Error::Format("invalid QOA frame header"));
+        }
+        let wave = wave.get_or_insert_with(|| {
+            let mut wave = Wave32::with_capacity(channels, sample_rate as f64, len);
+            wave.resize(len);
+            wave
+        });
+        if channels != wave.channels() || sample_rate as f64 != wave.sample_rate() {
+            return Err(Error::Format("QOA frames differ in format"));
+        }
+
+        lms.clear();
+        for _ in 0..channels {
+            let history = Lms::unpack(u64_be(take(8)?));
+            let weights = Lms::unpack(u64_be(take(8)?));
+            lms.push(Lms { history, weights });
+        }
+
+        for slice in (start..start + n).step_by(SLICE_LEN) {
+            let end = (slice + SLICE_LEN).min(start + n);
+            for (c, state) in lms.iter_mut().enumerate() {
+                let mut v = u64_be(take(8)?);
+                let scalefactor = (v >> 60) as usize;
+                for i in slice..end {
+                    let predicted = state.predict();
+                    let dequantized = dequantize(scalefactor, ((v >> 57) & 7) as u8);
+                    let reconstructed = clamp16(predicted + dequantized);
+                    wave.set(c, i, reconstructed as f32 / 32_767.0);
+                    state.update(reconstructed, dequantized);
+                    v <<= 3;
+                }
+            }
+        }
+        start += n;
+    }
+
+    Ok(wave.unwrap())
+}
+
+impl Asyn {
+    /// Render and write a QOA file.
+    pub fn write_qoa(&self, writer: impl Write) -> Result<(), Error> {
+        write_qoa(&self.clone().to_wav(), writer)
+    }
+
+    /// Render and save a QOA file.
+    pub fn save_qoa(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_qoa(&mut writer)?;
+        Ok(writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Tone, Waveform};
+
+    /// Peak and RMS error of a channel, against the clipped source.
+    fn error(source: &Wave32, decoded: &Wave32, channel: usize) -> (f32, f32) {
+        let diff = (0..source.len())
+            .map(|i| (source.at(channel, i).clamp(-1.0, 1.0) - decoded.at(channel, i)).abs());
+        let peak = diff.clone().fold(0.0, f32::max);
+        let rms = (diff.map(|d| d * d).sum::<f32>() / source.len() as f32).sqrt();
+        (peak, rms)
+    }
+
+    #[test]
+    fn round_trip() {
+        let rng = &mut funutd::Rnd::from_u64(13);
+        // QOA steps at most 0.44 of full scale per sample, so it smears the hard edges of naive
+        // square and saw waves. Each preset's envelope, pitch and filters go over a sine instead.
+        for (_, preset) in &crate::presets::PRESETS[..7] {
+            let asyn = Asyn {
+                tone: Tone::from(Waveform::Sine),
+                ..preset(rng)
+            };
+            let wave = asyn.clone().to_wav();
+            let mut qoa = Vec::new();
+            asyn.write_qoa(&mut qoa).unwrap();
+            // 64 bits per 20 samples, plus headers.
+            assert!(qoa.len() < wave.len() / 2 + 1024);
+
+            let decoded = read_qoa(&qoa[..]).unwrap();
+            assert_eq!(decoded.len(), wave.len());
+            assert_eq!(decoded.sample_rate(), wave.sample_rate());
+            let (_, rms) = error(&wave, &decoded, 0);
+            assert!(rms < 0.02, "rms {rms}");
+            // Past the first sample, which starts from silence.
+            let peak = (1..wave.len())
+                .map(|i| (wave.at(0, i) - decoded.at(0, i)).abs())
+                .fold(0.0, f32::max);
+            assert!(peak < 0.1, "peak {peak}");
+        }
+
+        // The unmodified renders. On hard edges the error is bounded by how fast QOA can slew, so
+        // compare against a reference that moves at most its largest step toward each sample. Most
+        // land within 0.01 of it; the margin leaves room for the predictor ringing on fast
+        // triangles (0.1 on a powerup).
+        let max_step = (SCALEFACTORS[15] as f32 * DEQUANT[6]) / 32_767.0;
+        for _ in 0..3 {
+            for (name, preset) in &crate::presets::PRESETS {
+                let asyn = preset(rng);
+                let wave = asyn.clone().to_wav();
+                let mut qoa = Vec::new();
+                write_qoa(&wave, &mut qoa).unwrap();
+                let (_, rms) = error(&wave, &read_qoa(&qoa[..]).unwrap(), 0);
+
+                let mut slewed = 0.0;
+                let slew = (0..wave.len())
+                    .map(|i| {
+                        let x = wave.at(0, i).clamp(-1.0, 1.0);
+                        slewed += (x - slewed).clamp(-max_step, max_step);
+                        (x - slewed).powi(2)
+                    })
+                    .sum::<f32>();
+                let slew = (slew / wave.len() as f32).sqrt();
+                assert!(rms < slew + 0.12, "{name}: rms {rms}, slew-limited {slew}");
+            }
+        }
+    }
+
+    #[test]
+    fn stereo_and_errors() {
+        // A partial frame of two channels.
+        let mut wave = Wave32::with_capacity(2, 22_050.0, 6000);
+        wave.resize(6000);
+        for i in 0..6000 {
+            let t = i as f32 / 22_050.0;
+            wave.set(0, i, (t * 440.0 * std::f32::consts::TAU).sin() * 0.5);
+            wave.set(1, i, (t * 660.0 * std::f32::consts::TAU).sin() * 0.5);
+        }
+        let mut qoa = Vec::new();
+        write_qoa(&wave, &mut qoa).unwrap();
+        let decoded = read_qoa(&qoa[..]).unwrap();
+        assert_eq!(decoded.channels(), 2);
+        for c in 0..2 {
+            let (peak, rms) = error(&wave, &decoded, c);
+            assert!(peak < 0.02 && rms < 0.005, "peak {peak}, rms {rms}");
+        }
+
+        // Full scale noise, the hardest input for the predictor.
+        let rng = &mut funutd::Rnd::from_u64(14);
+        let noise: Vec<f32> = (0..20_000).map(|_| rng.f32_in(-1.0, 1.0)).collect();
+        let mut qoa = Vec::new();
+        write_qoa(&Wave32::from_samples(44_100.0, &noise), &mut qoa).unwrap();
+        assert_eq!(read_qoa(&qoa[..]).unwrap().len(), noise.len());
+
+        assert!(matches!(
+            read_qoa(&qoa[..qoa.len() - 1]),
+            Err(Error::Format(_))
+        ));
+        assert!(matches!(read_qoa(&b"RIFF0000"[..]), Err(Error::Format(_))));
+        assert!(matches!(
+            write_qoa(&Wave32::new(1, 44_100.0), Vec::new()),
+            Err(Error::Invalid { .. })
+        ));
+    }
+}
@@ -0,0 +1,197 @@
+//! Versioned serde support for [`Asyn`].
+//!
+//! Every serialized [`Asyn`] carries a `version`. Older documents are migrated forward on load.
+//! Missing fields take their defaults. Deserialization goes through [`serde_json::Value`], so it
+//! needs a self-describing format (json, toml, ron, etc.).
+
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use crate::types::{Amplitude, Asyn, Filters, Pitch, Stereo, Tone};
+
+/// The current schema version. Documents without a version are treated as version 0.
+pub const SCHEMA_VERSION: u64 = 6;
+
+impl Serialize for Asyn {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Asyn", 8)?;
+        s.serialize_field("version", &SCHEMA_VERSION)?;
+        s.serialize_field("seed", &self.seed)?;
+        s.serialize_field("mutations", &self.mutations)?;
+        s.serialize_field("pitch", &self.pitch)?;
+        s.serialize_field("tone", &self.tone)?;
+        s.serialize_field("amplitude", &self.amplitude)?;
+        s.serialize_field("filters", &self.filters)?;
+        s.serialize_field("stereo", &self.stereo)?;
+        s.end()
+    }
+}
+
+/// The current layout, once migrated.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Fields {
+    seed: u64,
+    mutations: usize,
+    pitch: Pitch,
+    tone: Tone,
+    amplitude: Amplitude,
+    filters: Option<Filters>,
+    stereo: Option<Stereo>,
+}
+
+impl<'de> Deserialize<'de> for Asyn {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = Value::deserialize(deserializer)?;
+        let map = value
+            .as_object_mut()
+            .ok_or_else(|| de::Error::custom("expected a map"))?;
+        migrate(map).map_err(de::Error::custom)?;
+
+        let Fields {
+            seed,
+            mutations,
+            pitch,
+            tone,
+            amplitude,
+            filters,
+            stereo,
+        } = Fields::deserialize(value).map_err(de::Error::custom)?;
+        pitch.check_jumps().map_err(de::Error::custom)?;
+
+        Ok(Asyn {
+            seed,
+            mutations,
+            pitch,
+            tone,
+            amplitude,
+            filters,
+            stereo,
+        })
+    }
+}
+
+/// Bring a document up to [`SCHEMA_VERSION`], one version at a time. The version key is removed.
+fn migrate(map: &mut Map<String, Value>) -> Result<(), String> {
+    let version = match map.remove("version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or(format!("invalid version: {v}"))?,
+    };
+
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "version {version} is newer than supported ({SCHEMA_VERSION})"
+        ));
+    }
+
+    for version in version..SCHEMA_VERSION {
+        match version {
+            // Unversioned documents have the same layout as version 1.
+            0 => (),
+            // Filters gained normalization (on by default) and amplification. Keep old sounds
+            // as they were.
+            1 => {
+                if let Some(Value::Object(filters)) = map.get_mut("filters") {
+                    filters.entry("normalization").or_insert(Value::Bool(false));
+                }
+            }
+            // Stereo was added. Without it, sounds are mono as before.
+            2 => (),
+            // Flanger offsets were rendered as seconds, and are now milliseconds.
+            3 => {
+                if let Some(Value::Object(filters)) = map.get_mut("filters") {
+                    for key in ["flanger_offset", "flanger_offset_sweep"] {
+                        if let Some(v) = filters.get(key).and_then(Value::as_f64) {
+                            filters.insert(key.into(), serde_json::json!(v * 1000.0));
+                        }
+                    }
+                }
+            }
+            // Tones gained band-limiting, off by default.
+            4 => (),
+            // The two jumps became a list. Those that do nothing are left out.
+            5 => {
+                if let Some(Value::Object(pitch)) = map.get_mut("pitch") {
+                    let mut jumps = Vec::new();
+                    for (key, onset) in [("frequency_jump1", 0.33), ("frequency_jump2", 0.66)] {
+                        let jump = match pitch.remove(key) {
+                            Some(v) => <(f32, f32)>::deserialize(v).map_err(|e| e.to_string())?,
+                            None => (onset, 0.0),
+                        };
+                        if jump.1 != 0.0 {
+                            jumps.push(jump);
+                        }
+                    }
+                    jumps.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    pitch.insert("frequency_jumps".into(), serde_json::json!(jumps));
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_presets() {
+        use crate::presets::*;
+
+        let rng = &mut funutd::Rnd::from_u64(0);
+        for preset in [blip, explosion, hit, jump, laser, pickup, powerup, random] {
+            let asyn = preset(rng).mutate(rng);
+            let json = serde_json::to_string(&asyn).unwrap();
+            assert!(json.starts_with(&format!(r#"{{"version":{SCHEMA_VERSION},"#)));
+            assert_eq!(serde_json::from_str::<Asyn>(&json).unwrap(), asyn);
+        }
+    }
+
+    #[test]
+    fn migrate_old_versions() {
+        // Unversioned, with missing fields.
+        let asyn: Asyn =
+            serde_json::from_str(r#"{"seed":7,"tone":{"waveform":"Square"}}"#).unwrap();
+        assert_eq!(asyn.seed, 7);
+        assert_eq!(asyn.tone, Tone::from(crate::Waveform::Square));
+        assert_eq!(asyn.pitch, Pitch::default());
+
+        // Version 1 filters are not normalized.
+        let asyn: Asyn =
+            serde_json::from_str(r#"{"version":1,"filters":{"compression":2.0}}"#).unwrap();
+        assert_eq!(
+            asyn.filters,
+            Some(Filters {
+                compression: 2.0,
+                normalization: false,
+                ..Default::default()
+            })
+        );
+
+        // Version 3 flanger offsets are in seconds.
+        let asyn: Asyn = serde_json::from_str(
+            r#"{"version":3,"filters":{"flanger_offset":0.002,"flanger_offset_sweep":-0.001}}"#,
+        )
+        .unwrap();
+        let filters = asyn.filters.unwrap();
+        assert_eq!(filters.flanger_offset, 2.0);
+        assert_eq!(filters.flanger_offset_sweep, -1.0);
+
+        // Version 5 has two jumps.
+        let asyn: Asyn = serde_json::from_str(
+            r#"{"version":5,"pitch":{"frequency_jump1":[0.5,0.25],"frequency_jump2":[0.66,0.0]}}"#,
+        )
+        .unwrap();
+        assert_eq!(asyn.pitch.frequency_jumps, [(0.5, 0.25)]);
+        assert!(serde_json::from_str::<Asyn>(
+            r#"{"version":6,"pitch":{"frequency_jumps":[[0.5,0.25],[0.25,0.5]]}}"#
+        )
+        .is_err());
+
+        let newer = format!(r#"{{"version":{}}}"#, SCHEMA_VERSION + 1);
+        assert!(serde_json::from_str::<Asyn>(&newer).is_err());
+    }
+}
@@ -0,0 +1,796 @@
+//! Import of sfxr parameter strings, as written by as3sfxr ("copy settings") and bfxr.
+//!
+//! sfxr works in per-sample terms at 44.1kHz (8x supersampled for the oscillator and filters), so
+//! most parameters are converted by running the same formulas over the length of the sound. Some
+//! things don't map one to one, e.g. sfxr's exponential slide becomes our quadratic sweep. Those
+//! are listed in the [`SfxrReport`].
+
+use std::{error, f64::consts::TAU, fmt};
+
+use crate::types::{Amplitude, Asyn, Filters, Pitch, Tone, Waveform, MAX_HARMONICS};
+
+const SR: f64 = 44_100.0;
+/// The oscillator and filters run at 8x.
+const SUPER_SR: f64 = 8.0 * SR;
+
+/// as3sfxr settings string order.
+const SFXR_KEYS: [&str; 24] = [
+    "waveType",
+    "attackTime",
+    "sustainTime",
+    "sustainPunch",
+    "decayTime",
+    "startFrequency",
+    "minFrequency",
+    "slide",
+    "deltaSlide",
+    "vibratoDepth",
+    "vibratoSpeed",
+    "changeAmount",
+    "changeSpeed",
+    "squareDuty",
+    "dutySweep",
+    "repeatSpeed",
+    "phaserOffset",
+    "phaserSweep",
+    "lpFilterCutoff",
+    "lpFilterCutoffSweep",
+    "lpFilterResonance",
+    "hpFilterCutoff",
+    "hpFilterCutoffSweep",
+    "masterVolume",
+];
+
+/// bfxr settings string order.
+const BFXR_KEYS: [&str; 32] = [
+    "waveType",
+    "masterVolume",
+    "attackTime",
+    "sustainTime",
+    "sustainPunch",
+    "decayTime",
+    "compressionAmount",
+    "startFrequency",
+    "minFrequency",
+    "slide",
+    "deltaSlide",
+    "vibratoDepth",
+    "vibratoSpeed",
+    "overtones",
+    "overtoneFalloff",
+    "changeRepeat",
+    "changeAmount",
+    "changeSpeed",
+    "changeAmount2",
+    "changeSpeed2",
+    "squareDuty",
+    "dutySweep",
+    "repeatSpeed",
+    "flangerOffset",
+    "flangerSweep",
+    "lpFilterCutoff",
+    "lpFilterCutoffSweep",
+    "lpFilterResonance",
+    "hpFilterCutoff",
+    "hpFilterCutoffSweep",
+    "bitCrush",
+    "bitCrushSweep",
+];
+
+/// Parameters that range over -1..1. The rest, other than the wave type, range over 0..1.
+const SIGNED_KEYS: [&str; 11] = [
+    "slide",
+    "deltaSlide",
+    "changeAmount",
+    "changeAmount2",
+    "dutySweep",
+    "phaserOffset",
+    "phaserSweep",
+    "flangerOffset",
+    "flangerSweep",
+    "lpFilterCutoffSweep",
+    "hpFilterCutoffSweep",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fidelity {
+    /// Close enough that it shouldn't be audible.
+    Exact,
+    /// Mapped onto something similar.
+    Approximate,
+    /// Left out.
+    Dropped,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SfxrIssue {
+    pub parameter: &'static str,
+    pub fidelity: Fidelity,
+    pub reason: &'static str,
+}
+
+/// How well an sfxr sound was carried over.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SfxrReport {
+    /// The number of parameters that were set to something other than their neutral value.
+    pub active: usize,
+    /// Active parameters that didn't map exactly.
+    pub issues: Vec<SfxrIssue>,
+}
+
+impl SfxrReport {
+    /// 1.0 if everything mapped exactly, down to 0.0 if every active parameter was dropped.
+    /// Approximations count for half.
+    pub fn fidelity(&self) -> f32 {
+        if self.active == 0 {
+            return 1.0;
+        }
+        let lost: f32 = self
+            .issues
+            .iter()
+            .map(|i| match i.fidelity {
+                Fidelity::Exact => 0.0,
+                Fidelity::Approximate => 0.5,
+                Fidelity::Dropped => 1.0,
+            })
+            .sum();
+        (1.0 - lost / self.active as f32).max(0.0)
+    }
+
+    fn push(&mut self, parameter: &'static str, fidelity: Fidelity, reason: &'static str) {
+        self.issues.push(SfxrIssue {
+            parameter,
+            fidelity,
+            reason,
+        });
+    }
+}
+
+impl fmt::Display for SfxrReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fidelity: {:.0}%", self.fidelity() * 100.0)?;
+        for i in &self.issues {
+            write!(f, "; {} ({:?}): {}", i.parameter, i.fidelity, i.reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SfxrError {
+    /// The wrong number of comma separated values.
+    Length { expected: usize, found: usize },
+    InvalidNumber {
+        parameter: &'static str,
+        value: String,
+    },
+    /// A number outside of the parameter's normalized range.
+    OutOfRange { parameter: &'static str, value: f64 },
+}
+
+impl fmt::Display for SfxrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Length { expected, found } => {
+                write!(f, "expected {expected} parameters, found {found}")
+            }
+            Self::InvalidNumber { parameter, value } => {
+                write!(f, "invalid value for {parameter}: {value:?}")
+            }
+            Self::OutOfRange { parameter, value } => {
+                write!(f, "{parameter} out of range: {value}")
+            }
+        }
+    }
+}
+
+impl error::Error for SfxrError {}
+
+/// Split a settings string. Empty values are zero; trailing empty values are ignored. Every value
+/// must be finite and in its normalized range, and the wave type a whole number.
+fn parse_values<const N: usize>(s: &str, keys: [&'static str; N]) -> Result<[f64; N], SfxrError> {
+    let mut parts: Vec<&str> = s.trim().split(',').map(str::trim).collect();
+    while parts.len() > N && parts.last() == Some(&"") {
+        parts.pop();
+    }
+    if parts.len() != N {
+        return Err(SfxrError::Length {
+            expected: N,
+            found: parts.len(),
+        });
+    }
+
+    let mut values = [0.0f64; N];
+    for ((value, part), parameter) in values.iter_mut().zip(parts).zip(keys) {
+        // The wave type is checked against its list when it's matched.
+        let wave = parameter == "waveType";
+        if !part.is_empty() {
+            let invalid = || SfxrError::InvalidNumber {
+                parameter,
+                value: part.to_string(),
+            };
+            *value = part.parse().map_err(|_| invalid())?;
+            if !value.is_finite() || (wave && (*value < 0.0 || value.fract() != 0.0)) {
+                return Err(invalid());
+            }
+        }
+        let range = if SIGNED_KEYS.contains(&parameter) {
+            -1.0..=1.0
+        } else {
+            0.0..=1.0
+        };
+        if !wave && !range.contains(value) {
+            return Err(SfxrError::OutOfRange {
+                parameter,
+                value: *value,
+            });
+        }
+    }
+    Ok(values)
+}
+
+/// The union of sfxr and bfxr parameters, in sfxr's normalized ranges.
+#[derive(Default)]
+struct Params {
+    wave: Waveform,
+    attack: f64,
+    sustain: f64,
+    punch: f64,
+    decay: f64,
+    start_frequency: f64,
+    min_frequency: f64,
+    slide: f64,
+    delta_slide: f64,
+    vibrato_depth: f64,
+    vibrato_speed: f64,
+    change_amount: f64,
+    change_speed: f64,
+    square_duty: f64,
+    duty_sweep: f64,
+    repeat_speed: f64,
+    phaser_offset: f64,
+    phaser_sweep: f64,
+    lpf_cutoff: f64,
+    lpf_sweep: f64,
+    lpf_resonance: f64,
+    hpf_cutoff: f64,
+    hpf_sweep: f64,
+    master_volume: f64,
+    // bfxr only.
+    compression: f64,
+    overtones: f64,
+    overtone_falloff: f64,
+    change_repeat: f64,
+    change_amount2: f64,
+    change_speed2: f64,
+    bit_crush: f64,
+    bit_crush_sweep: f64,
+}
+
+impl Asyn {
+    /// Import an as3sfxr settings string (24 comma separated values).
+    pub fn from_sfxr(settings: &str) -> Result<(Asyn, SfxrReport), SfxrError> {
+        let v = parse_values(settings, SFXR_KEYS)?;
+        let mut report = SfxrReport::default();
+
+        let wave = match v[0] as u32 {
+            0 => Waveform::Square,
+            1 => Waveform::Saw,
+            2 => Waveform::Sine,
+            3 => Waveform::White,
+            _ => {
+                return Err(SfxrError::InvalidNumber {
+                    parameter: "waveType",
+                    value: v[0].to_string(),
+                })
+            }
+        };
+
+        let params = Params {
+            wave,
+            attack: v[1],
+            sustain: v[2],
+            punch: v[3],
+            decay: v[4],
+            start_frequency: v[5],
+            min_frequency: v[6],
+            slide: v[7],
+            delta_slide: v[8],
+            vibrato_depth: v[9],
+            vibrato_speed: v[10],
+            change_amount: v[11],
+            change_speed: v[12],
+            square_duty: v[13],
+            duty_sweep: v[14],
+            repeat_speed: v[15],
+            phaser_offset: v[16],
+            phaser_sweep: v[17],
+            lpf_cutoff: v[18],
+            lpf_sweep: v[19],
+            lpf_resonance: v[20],
+            hpf_cutoff: v[21],
+            hpf_sweep: v[22],
+            master_volume: v[23],
+            ..Default::default()
+        };
+
+        let asyn = params.to_asyn(&mut report);
+        Ok((asyn, report))
+    }
+
+    /// Import a bfxr settings string (32 comma separated values).
+    pub fn from_bfxr(settings: &str) -> Result<(Asyn, SfxrReport), SfxrError> {
+        let v = parse_values(settings, BFXR_KEYS)?;
+        let mut report = SfxrReport::default();
+
+        let wave = match v[0] as u32 {
+            0 => Waveform::Square,
+            1 => Waveform::Saw,
+            2 => Waveform::Sine,
+            3 => Waveform::White,
+            4 => Waveform::Triangle,
+            5 => Waveform::Pink,
+            6 => Waveform::Tangent,
+            7 => Waveform::Whistle,
+            8 => Waveform::Breaker,
+            9 => {
+                report.push("waveType", Fidelity::Approximate, "1-bit noise is white");
+                Waveform::White
+            }
+            10 => {
+                report.push("waveType", Fidelity::Approximate, "buzz is a sawtooth");
+                Waveform::Saw
+            }
+            _ => {
+                return Err(SfxrError::InvalidNumber {
+                    parameter: "waveType",
+                    value: v[0].to_string(),
+                })
+            }
+        };
+
+        let params = Params {
+            wave,
+            master_volume: v[1],
+            attack: v[2],
+            sustain: v[3],
+            punch: v[4],
+            decay: v[5],
+            compression: v[6],
+            start_frequency: v[7],
+            min_frequency: v[8],
+            slide: v[9],
+            delta_slide: v[10],
+            vibrato_depth: v[11],
+            vibrato_speed: v[12],
+            overtones: v[13],
+            overtone_falloff: v[14],
+            change_repeat: v[15],
+            change_amount: v[16],
+            change_speed: v[17],
+            change_amount2: v[18],
+            change_speed2: v[19],
+            square_duty: v[20],
+            duty_sweep: v[21],
+            repeat_speed: v[22],
+            // bfxr's flanger is sfxr's phaser.
+            phaser_offset: v[23],
+            phaser_sweep: v[24],
+            lpf_cutoff: v[25],
+            lpf_sweep: v[26],
+            lpf_resonance: v[27],
+            hpf_cutoff: v[28],
+            hpf_sweep: v[29],
+            bit_crush: v[30],
+            bit_crush_sweep: v[31],
+        };
+
+        let asyn = params.to_asyn(&mut report);
+        Ok((asyn, report))
+    }
+}
+
+/// sfxr's change (arpeggio) limit in samples. Zero never changes.
+fn change_limit(speed: f64) -> f64 {
+    if speed >= 1.0 {
+        0.0
+    } else {
+        (1.0 - speed).powi(2) * 20_000.0 + 32.0
+    }
+}
+
+/// sfxr's change multiplier as a frequency jump.
+fn change_jump(amount: f64) -> f64 {
+    let period = if amount >= 0.0 {
+        1.0 - amount * amount * 0.9
+    } else {
+        1.0 + amount * amount * 10.0
+    };
+    1.0 / period - 1.0
+}
+
+/// Least squares fit of `y = a * t + b * t^2` (t in 0..1) to the samples. Returns `(a, b)` and the
+/// rms error.
+fn fit_quadratic(ys: &[f64]) -> (f64, f64, f64) {
+    let n = ys.len().max(1) as f64;
+    let (mut t2, mut t3, mut t4, mut ty, mut t2y) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (i, y) in ys.iter().enumerate() {
+        let t = i as f64 / n;
+        t2 += t * t;
+        t3 += t * t * t;
+        t4 += t * t * t * t;
+        ty += t * y;
+        t2y += t * t * y;
+    }
+    let det = t2 * t4 - t3 * t3;
+    if det.abs() < 1e-12 {
+        return (0.0, 0.0, 0.0);
+    }
+    let a = (ty * t4 - t2y * t3) / det;
+    let b = (t2 * t2y - t3 * ty) / det;
+
+    let rms = (ys
+        .iter()
+        .enumerate()
+        .map(|(i, y)| {
+            let t = i as f64 / n;
+            (y - a * t - b * t * t).powi(2)
+        })
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    (a, b, rms)
+}
+
+impl Params {
+    fn to_asyn(&self, report: &mut SfxrReport) -> Asyn {
+        use Fidelity::*;
+
+        let p = self;
+        let mut active = |v: f64, neutral: f64| {
+            if v != neutral {
+                report.active += 1;
+            }
+        };
+        for v in [
+            p.attack,
+            p.sustain,
+            p.punch,
+            p.decay,
+            p.start_frequency,
+            p.min_frequency,
+            p.slide,
+            p.delta_slide,
+            p.vibrato_depth,
+            p.vibrato_speed,
+            p.change_amount,
+            p.change_speed,
+            p.duty_sweep,
+            p.repeat_speed,
+            p.phaser_offset,
+            p.phaser_sweep,
+            p.lpf_sweep,
+            p.lpf_resonance,
+            p.hpf_cutoff,
+            p.hpf_sweep,
+            p.compression,
+            p.overtones,
+            p.overtone_falloff,
+            p.change_repeat,
+            p.change_amount2,
+            p.change_speed2,
+            p.bit_crush,
+            p.bit_crush_sweep,
+            p.square_duty,
+        ] {
+            active(v, 0.0);
+        }
+        active(p.lpf_cutoff, 1.0);
+        active(p.master_volume, 0.5);
+
+        // Envelope, in samples.
+        let env = |v: f64| v * v * 100_000.0;
+        let total = env(p.attack) + env(p.sustain) + env(p.decay);
+
+        // sfxr's sustain starts at 1 + 2 * punch and falls to 1. Ours falls from 1 to 1 - punch.
+        let amplitude = Amplitude {
+            attack: (env(p.attack) / SR) as f32,
+            sustain: (env(p.sustain) / SR) as f32,
+            punch: (2.0 * p.punch / (1.0 + 2.0 * p.punch)) as f32,
+            decay: (env(p.decay) / SR) as f32,
+            ..Default::default()
+        };
+
+        // Repeat resets the frequency, slide, change and duty. Sweeps are per repeat.
+        let repeat = if p.repeat_speed > 0.0 {
+            (1.0 - p.repeat_speed).powi(2) * 20_000.0 + 32.0
+        } else {
+            0.0
+        };
+        let span = if repeat > 0.0 {
+            repeat.min(total)
+        } else {
+            total
+        };
+
+        // Noise makes 32 values per period. Ours makes two.
+        let noise = matches!(p.wave, Waveform::White | Waveform::Pink | Waveform::Brown);
+        let scale = if noise { 16.0 } else { 1.0 };
+
+        // Run the slide over one repeat.
+        let min_period = 100.0 / (p.min_frequency * p.min_frequency + 0.001);
+        let mut period = 100.0 / (p.start_frequency * p.start_frequency + 0.001);
+        let mut slide = 1.0 - p.slide.powi(3) * 0.01;
+        let delta_slide = -p.delta_slide.powi(3) * 0.000_001;
+        let frequency = SUPER_SR / period;
+        let mut cut_off = false;
+        let curve: Vec<f64> = (0..span as usize)
+            .map(|_| {
+                // sfxr's shortest period is 8 (supersampled) samples.
+                let f = SUPER_SR / period.max(8.0) - frequency;
+                slide += delta_slide;
+                period *= slide;
+                if period > min_period {
+                    period = min_period;
+                    cut_off |= p.min_frequency > 0.0;
+                }
+                f
+            })
+            .collect();
+        let (sweep, delta_sweep, rms) = fit_quadratic(&curve);
+        if (p.slide != 0.0 || p.delta_slide != 0.0) && rms > 0.01 * frequency {
+            let name = if p.delta_slide != 0.0 {
+                "deltaSlide"
+            } else {
+                "slide"
+            };
+            report.push(name, Approximate, "exponential slide fitted with a sweep");
+        }
+        if cut_off {
+            report.push(
+                "minFrequency",
+                Dropped,
+                "the sound isn't cut off when the slide reaches it",
+            );
+        }
+
+        let mut pitch = Pitch {
+            frequency: (frequency * scale) as f32,
+            frequency_sweep: (sweep * scale) as f32,
+            frequency_delta_sweep: (delta_sweep * scale) as f32,
+            repeat_frequency: if repeat > 0.0 {
+                (SR / repeat) as f32
+            } else {
+                0.0
+            },
+            ..Default::default()
+        };
+
+        if p.vibrato_depth > 0.0 {
+            pitch.vibrato_depth = (frequency * scale * p.vibrato_depth * 0.5) as f32;
+            pitch.vibrato_frequency = (p.vibrato_speed.powi(2) * 0.01 * SR / TAU) as f32;
+            report.push(
+                "vibratoDepth",
+                Approximate,
+                "depth is relative to the start frequency",
+            );
+        }
+
+        // Changes past the end of the (repeated) sound never happen, in sfxr either.
+        let jump = |amount: f64, speed: f64| {
+            let limit = change_limit(speed);
+            (amount != 0.0 && limit > 0.0 && limit < span)
+                .then(|| ((limit / span) as f32, change_jump(amount) as f32))
+        };
+        pitch = pitch.with_jumps(
+            [
+                jump(p.change_amount, p.change_speed),
+                jump(p.change_amount2, p.change_speed2),
+            ]
+            .into_iter()
+            .flatten(),
+        );
+        if p.change_repeat > 0.0 {
+            report.push(
+                "changeRepeat",
+                Dropped,
+                "jumps only repeat with the whole sound",
+            );
+        }
+
+        let mut tone = Tone {
+            waveform: p.wave,
+            interpolate_noise: false,
+            ..Default::default()
+        };
+        if p.wave == Waveform::Square {
+            tone.square_duty = (0.5 - p.square_duty * 0.5) as f32;
+            tone.square_duty_sweep = (-p.duty_sweep * 0.000_05 * span) as f32;
+            let end = tone.square_duty + tone.square_duty_sweep;
+            if !(0.0..=0.5).contains(&end) {
+                report.push(
+                    "dutySweep",
+                    Approximate,
+                    "sfxr stops the sweep at 0 and 50%",
+                );
+            }
+        }
+        if p.overtones > 0.0 {
+            let n = (p.overtones * 10.0).round() as u32;
+            tone.harmonics = n.min(MAX_HARMONICS);
+            tone.harmonics_falloff = (1.0 - p.overtone_falloff) as f32;
+            report.push(
+                "overtones",
+                Approximate,
+                "at most 5 harmonics, with a different falloff curve",
+            );
+        }
+
+        // sfxr doesn't normalize.
+        let mut filters = Filters {
+            normalization: false,
+            ..Default::default()
+        };
+        let mut any = false;
+
+        if p.phaser_offset != 0.0 || p.phaser_sweep != 0.0 {
+            let ms = |samples: f64| (samples / SR * 1000.0) as f32;
+            filters.flanger_offset = ms(p.phaser_offset.powi(2) * 1020.0);
+            filters.flanger_offset_sweep =
+                ms(p.phaser_sweep.powi(2) * p.phaser_sweep.signum() * total);
+            report.push("phaserOffset", Approximate, "the phaser is a flanger");
+            any = true;
+        }
+
+        if p.lpf_cutoff < 1.0 {
+            // Two pole resonant filter; the natural frequency is sqrt(w).
+            let hz = |w: f64| (w.clamp(0.0, 0.1).sqrt() * SUPER_SR / TAU).min(22_050.0);
+            let w = p.lpf_cutoff.powi(3) * 0.1;
+            // The cutoff sweeps every supersample.
+            let end = w * (1.0 + p.lpf_sweep * 0.0001).powf(8.0 * total);
+            filters.low_pass_cutoff = hz(w) as f32;
+            filters.low_pass_sweep = (hz(end) - hz(w)) as f32;
+            report.push(
+                "lpFilterCutoff",
+                Approximate,
+                "the low pass filter has one pole",
+            );
+            any = true;
+        }
+        if p.lpf_resonance > 0.0 {
+            report.push("lpFilterResonance", Dropped, "no resonance");
+        }
+
+        if p.hpf_cutoff > 0.0 {
+            let hz = |w: f64| (-(1.0 - w.clamp(0.000_01, 0.1)).ln() * SUPER_SR / TAU).min(22_050.0);
+            let w = p.hpf_cutoff.powi(2) * 0.1;
+            // Unlike the low pass, the cutoff sweeps once per output sample.
+            let end = w * (1.0 + p.hpf_sweep * 0.0003).powf(total);
+            filters.high_pass_cutoff = hz(w) as f32;
+            filters.high_pass_sweep = (hz(end) - hz(w)) as f32;
+            any = true;
+        }
+
+        if p.compression > 0.0 {
+            filters.compression = (1.0 / (1.0 + 4.0 * p.compression)) as f32;
+            any = true;
+        }
+
+        if p.bit_crush > 0.0 || p.bit_crush_sweep != 0.0 {
+            report.push(
+                "bitCrush",
+                Dropped,
+                "bfxr's bit crush reduces the sample rate",
+            );
+        }
+
+        if p.master_volume != 0.5 {
+            // The volume is squared, and our level is set by the default.
+            filters.amplification = (p.master_volume / 0.5).powi(2) as f32;
+            report.push(
+                "masterVolume",
+                Approximate,
+                "relative to the default volume",
+            );
+            any = true;
+        }
+
+        Asyn {
+            pitch,
+            tone,
+            amplitude,
+            filters: any.then_some(filters),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_sfxr() -> Result<(), crate::Error> {
+        // as3sfxr "jump" with a slide.
+        let (asyn, report) =
+            Asyn::from_sfxr("0,,0.2193,,0.4748,0.3482,,0.2988,,,,,,0.3,,,,,1,,,,,0.5").unwrap();
+
+        assert_eq!(asyn.tone.waveform, Waveform::Square);
+        assert!((asyn.pitch.frequency - 431.3).abs() < 0.1);
+        assert!(asyn.pitch.frequency_sweep > 0.0);
+        assert!((asyn.amplitude.sustain - 0.109).abs() < 0.001);
+        assert_eq!(asyn.tone.square_duty, 0.35);
+        assert!(asyn.filters.is_none());
+        assert!(
+            report.fidelity() > 0.5 && report.fidelity() < 1.0,
+            "{report}"
+        );
+        assert_eq!(report.issues[0].parameter, "slide");
+
+        asyn.save_wav("sfxr_jump.wav", crate::SampleFormat::I16)
+    }
+
+    #[test]
+    fn import_bfxr() {
+        let (asyn, report) =
+            Asyn::from_bfxr("1,0.5,,0.1,0.4,0.3,0.3,0.5,,,,,,,,,0.4,0.6,,,,,,,,0.6,,0.5,,,,")
+                .unwrap();
+
+        assert_eq!(asyn.tone.waveform, Waveform::Saw);
+        assert!((asyn.amplitude.punch - 0.8 / 1.8).abs() < 1e-6);
+        assert!(asyn.pitch.frequency_jumps[0].1 > 0.0);
+        let filters = asyn.filters.as_ref().unwrap();
+        assert!((filters.compression - 1.0 / 2.2).abs() < 1e-6);
+        assert!(filters.low_pass_cutoff < 22_050.0);
+        assert!(!filters.normalization);
+        assert!(report
+            .issues
+            .iter()
+            .any(|i| i.parameter == "lpFilterResonance" && i.fidelity == Fidelity::Dropped));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Asyn::from_sfxr("0,1,2"),
+            Err(SfxrError::Length {
+                expected: 24,
+                found: 3
+            })
+        );
+        assert!(matches!(
+            Asyn::from_bfxr(&format!("x{}", ",".repeat(31))),
+            Err(SfxrError::InvalidNumber {
+                parameter: "waveType",
+                ..
+            })
+        ));
+
+        let sfxr = |wave: &str, sustain: &str| {
+            Asyn::from_sfxr(&format!("{wave},,{sustain},,0.4,0.3,,,,,,,,,,,,,1,,,,,0.5"))
+        };
+        for wave in ["-1", "1.5", "NaN"] {
+            assert!(matches!(
+                sfxr(wave, "0.2"),
+                Err(SfxrError::InvalidNumber {
+                    parameter: "waveType",
+                    ..
+                })
+            ));
+        }
+        assert!(matches!(
+            sfxr("0", "inf"),
+            Err(SfxrError::InvalidNumber {
+                parameter: "sustainTime",
+                ..
+            })
+        ));
+        assert_eq!(
+            sfxr("0", "1000"),
+            Err(SfxrError::OutOfRange {
+                parameter: "sustainTime",
+                value: 1000.0
+            })
+        );
+    }
+}
@@ -2,13 +2,13 @@ use std::fmt;
 
 use flagset::{flags, FlagSet};
 use fundsp::hacker32::{
-    clamp, clamp01, constant, dc, flanger, fract, highpole, lerp, lerp11, lfo, lfo2, lowpole,
-    lowpole_hz, map, pass, pinkpass, round, sin_hz, sine, sink, An, AttoHash, AudioNode,
-    AudioUnit32, Float, Frame, Net32, Num, Sine, Wave32, DEFAULT_SR, U0, U1, U2,
+    clamp, clamp01, constant, dc, delay, flanger, fract, highpole, lerp, lerp11, lfo, lfo2,
+    lowpole, lowpole_hz, map, pass, pinkpass, round, sin_hz, sine, sink, An, AttoHash, AudioNode,
+    AudioUnit32, Float, Frame, Net32, Num, Sine, Wave32, DEFAULT_SR, U0, U1, U2, U3,
 };
 use funutd::Rnd;
 
-use crate::osc;
+use crate::{loudness, osc, oversample};
 
 /// Vibrato.
 pub fn vibrato(depth: f32, frequency: f32) -> An<impl AudioNode> {
@@ -31,7 +31,7 @@ pub fn wrap(unit: impl AudioUnit32 + 'static) -> Net32 {
     Net32::wrap(unit)
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Asyn {
     pub seed: u64,
     pub mutations: usize,
@@ -39,6 +39,8 @@ pub struct Asyn {
     pub tone: Tone,
     pub amplitude: Amplitude,
     pub filters: Option<Filters>,
+    /// Without this the sound is mono.
+    pub stereo: Option<Stereo>,
 }
 
 impl Asyn {
@@ -48,20 +50,35 @@ impl Asyn {
         self.tone = self.tone.mutate(rng);
         self.amplitude = self.amplitude.mutate(rng);
         self.filters = Some(self.filters.unwrap_or_default().mutate(rng));
+        // Mono sounds stay mono.
+        self.stereo = self.stereo.map(|s| s.mutate(rng));
         self
     }
 
+    /// The length of the render: the envelope, then the tail of the effects.
     pub fn len(&self) -> f32 {
-        self.amplitude.len()
+        self.amplitude.len() + self.tail()
+    }
+
+    /// How long the effects ring on after the envelope ends, in seconds.
+    pub fn tail(&self) -> f32 {
+        self.filters.as_ref().map_or(0.0, Filters::tail) + self.stereo.map_or(0.0, |s| s.tail())
     }
 
     pub fn to_net(self) -> Net32 {
+        self.to_net_at(DEFAULT_SR)
+    }
+
+    /// The net for playing or rendering at `sample_rate`. Oscillators pick up the rate when the
+    /// net is reset (as fundsp's do), but filter limits depend on it.
+    pub fn to_net_at(self, sample_rate: f64) -> Net32 {
         let Asyn {
             seed,
             pitch,
             tone,
             amplitude,
             filters,
+            stereo,
             ..
         } = self;
 
@@ -70,7 +87,10 @@ impl Asyn {
 
         let mut net = pitch.to_net(len1) >> (tone.to_net(len1) * amplitude.to_net());
         if let Some(f) = filters {
-            net = net >> f.to_net(len1);
+            net = net >> f.to_net(len1, sample_rate);
+        }
+        if let Some(s) = stereo {
+            net = net >> s.to_net(len1);
         }
 
         // This makes it so there's no random variance with the same seed.
@@ -80,22 +100,122 @@ impl Asyn {
     }
 
     pub fn to_wav(self) -> Wave32 {
-        println!("to_wav: {}", &self);
-        Wave32::render(DEFAULT_SR, self.len() as f64, &mut self.to_net())
+        self.to_wav_at(DEFAULT_SR)
+    }
+
+    pub fn to_wav_at(self, sample_rate: f64) -> Wave32 {
+        self.render(&RenderOptions {
+            sample_rate,
+            ..Default::default()
+        })
+    }
+
+    pub fn render(self, options: &RenderOptions) -> Wave32 {
+        let factor = Ord::max(options.oversample, 1);
+        let sample_rate = options.sample_rate * factor as f64;
+        let filters = self.filters.clone();
+        let len = self.len() as f64;
+        let mut wave = Wave32::render(sample_rate, len, &mut self.to_net_at(sample_rate));
+        if factor > 1 {
+            wave = oversample::decimate(&wave, factor);
+            // The same length as without oversampling.
+            wave.resize((len * options.sample_rate).round() as usize);
+        }
+        if let Some(f) = filters {
+            f.apply_gain(&mut wave);
+        }
+        if let Some(target) = options.loudness {
+            loudness::normalize_loudness(&mut wave, target);
+        }
+        if let Some(threshold) = options.trim {
+            trim_tail(&mut wave, threshold);
+        }
+        wave
     }
 }
 
+/// Options for [`Asyn::render`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderOptions {
+    pub sample_rate: f64,
+    /// Remove trailing samples quieter than this in every channel (e.g. 0.001 for -60 dB).
+    pub trim: Option<f32>,
+    /// Render at this multiple of the sample rate, then filter and decimate, to reduce aliasing
+    /// from the oscillators. 1 (or 0) renders at the sample rate.
+    pub oversample: usize,
+    /// Scale to this integrated loudness in LUFS (e.g. -16), after normalization and
+    /// amplification, up to a true peak of [`loudness::TRUE_PEAK_CEILING`]. See
+    /// [`crate::loudness`].
+    pub loudness: Option<f32>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: DEFAULT_SR,
+            trim: None,
+            oversample: 1,
+            loudness: None,
+        }
+    }
+}
+
+/// Cut the wave after the last sample at or above `threshold` in any channel.
+pub fn trim_tail(wave: &mut Wave32, threshold: f32) {
+    let end = (0..wave.len())
+        .rposition(|i| (0..wave.channels()).any(|c| wave.at(c, i).abs() >= threshold))
+        .map_or(0, |i| i + 1);
+    wave.resize(end);
+}
+
+/// The alternate form (`{:#}`) prints every field at full precision, including the seed and
+/// mutations. It parses back (see [`std::str::FromStr`]) to the exact same values.
 impl fmt::Display for Asyn {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}] [{}] [{}]", self.pitch, self.tone, self.amplitude)?;
-        if let Some(filters) = self.filters.as_ref() {
-            write!(f, "[{}]", filters)?;
+        if f.alternate() {
+            write!(
+                f,
+                "[{:#}] [{:#}] [{:#}]",
+                self.pitch, self.tone, self.amplitude
+            )?;
+            if let Some(filters) = self.filters.as_ref() {
+                write!(f, "[{:#}]", filters)?;
+            }
+            if let Some(stereo) = self.stereo.as_ref() {
+                write!(f, "[{:#}]", stereo)?;
+            }
+            write!(f, " [seed: {} mutations: {}]", self.seed, self.mutations)
+        } else {
+            write!(f, "[{}] [{}] [{}]", self.pitch, self.tone, self.amplitude)?;
+            if let Some(filters) = self.filters.as_ref() {
+                write!(f, "[{}]", filters)?;
+            }
+            if let Some(stereo) = self.stereo.as_ref() {
+                write!(f, "[{}]", stereo)?;
+            }
+            Ok(())
         }
-        Ok(())
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+/// Prints with the given precision, or at full precision if there isn't one.
+struct Prec(f32, Option<usize>);
+
+impl fmt::Display for Prec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            Some(p) => write!(f, "{:.*}", p, self.0),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Pitch {
     pub frequency: f32,
     pub frequency_sweep: f32,
@@ -104,14 +224,14 @@ pub struct Pitch {
     pub vibrato_frequency: f32,
     // This does nothing without sweep.
     pub repeat_frequency: f32,
-    pub frequency_jump1: (f32, f32), // onset %, amount %
-    pub frequency_jump2: (f32, f32),
+    /// `(onset, amount)` fractions, sorted by onset. After each onset the frequency is
+    /// multiplied by `1 + amount`, so amounts are above -1. See [`Pitch::with_jumps`] and
+    /// [`Pitch::check_jumps`].
+    pub frequency_jumps: Vec<(f32, f32)>,
 }
 
 const FREQUENCY_DEFAULT: f32 = 500.0;
 const VIBRATO_FREQUENCY_DEFAULT: f32 = 10.0;
-const FREQUENCY_JUMP1_ONSET_DEFAULT: f32 = 0.33;
-const FREQUENCY_JUMP2_ONSET_DEFAULT: f32 = 0.66;
 
 impl Default for Pitch {
     fn default() -> Self {
@@ -122,38 +242,39 @@ impl Default for Pitch {
             vibrato_depth: 0.0,
             vibrato_frequency: VIBRATO_FREQUENCY_DEFAULT,
             repeat_frequency: 0.0,
-            frequency_jump1: (FREQUENCY_JUMP1_ONSET_DEFAULT, 0.0),
-            frequency_jump2: (FREQUENCY_JUMP2_ONSET_DEFAULT, 0.0),
+            frequency_jumps: Vec::new(),
         }
     }
 }
 
 impl fmt::Display for Pitch {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:.0}hz", self.frequency)?;
-        if self.frequency_sweep != 0.0 {
-            write!(f, " sweep: {:.0}", self.frequency_sweep)?;
+        let all = f.alternate();
+        let p = |v, prec| Prec(v, (!all).then_some(prec));
+
+        write!(f, "{}hz", p(self.frequency, 0))?;
+        if all || self.frequency_sweep != 0.0 {
+            write!(f, " sweep: {}", p(self.frequency_sweep, 0))?;
         }
-        if self.frequency_delta_sweep != 0.0 {
-            write!(f, " delta sweep: {:.0}", self.frequency_delta_sweep)?;
+        if all || self.frequency_delta_sweep != 0.0 {
+            write!(f, " delta sweep: {}", p(self.frequency_delta_sweep, 0))?;
         }
-        if self.vibrato_depth > 0.0 && self.vibrato_frequency > 0.0 {
+        if all || self.vibrato_depth > 0.0 && self.vibrato_frequency > 0.0 {
             write!(
                 f,
-                " vibrato: ({:.0}, {:.0})",
-                self.vibrato_depth, self.vibrato_frequency
+                " vibrato: ({}, {})",
+                p(self.vibrato_depth, 0),
+                p(self.vibrato_frequency, 0)
             )?;
         }
-        if self.repeat_frequency > 0.0 {
-            write!(f, " repeat: {:.0}", self.repeat_frequency)?;
+        if all || self.repeat_frequency > 0.0 {
+            write!(f, " repeat: {}", p(self.repeat_frequency, 0))?;
         }
-        let (onset, amount) = self.frequency_jump1;
-        if amount > 0.0 {
-            write!(f, " jump1: ({onset:.2}, {amount:.2})")?;
-        }
-        let (onset, amount) = self.frequency_jump2;
-        if amount > 0.0 {
-            write!(f, " jump2: ({onset:.2}, {amount:.2})")?;
+        if !self.frequency_jumps.is_empty() {
+            write!(f, " jumps:")?;
+            for (onset, amount) in &self.frequency_jumps {
+                write!(f, " ({}, {})", p(*onset, 2), p(*amount, 2))?;
+            }
         }
         Ok(())
     }
@@ -188,16 +309,63 @@ impl Pitch {
         #[rustfmt::skip]
         mutate_f32!(self.vibrato_frequency, rng, VIBRATO_FREQUENCY_DEFAULT, 0.0, 1_000.0, 1.0);
         mutate_f32!(self.repeat_frequency, rng, 0.0, 0.0, 100.0, 0.1);
-        #[rustfmt::skip]
-        mutate_f32!(self.frequency_jump1.0, rng, FREQUENCY_JUMP1_ONSET_DEFAULT, 0.0, 1.0, 0.05);
-        mutate_f32!(self.frequency_jump1.1, rng, 0.0, 0.0, 1.0, 0.05);
-        #[rustfmt::skip]
-        mutate_f32!(self.frequency_jump2.0, rng, FREQUENCY_JUMP2_ONSET_DEFAULT, 0.0, 1.0, 0.05);
-        mutate_f32!(self.frequency_jump2.1, rng, 0.0, 0.0, 1.0, 0.05);
+        for (onset, amount) in &mut self.frequency_jumps {
+            mutate_f32!(*onset, rng, 0.0, 0.0, 1.0, 0.05);
+            // Short of -1, which leaves no frequency.
+            mutate_f32!(*amount, rng, 0.0, -0.95, 1.0, 0.05);
+        }
+        // Sometimes gain a jump, less often the more there are.
+        if rng.bool(0.3 * 0.5f64.powi(self.frequency_jumps.len() as i32)) {
+            self.frequency_jumps.push((
+                round_to(rng.f32(), 0.05),
+                round_to(rng.f32_in(-0.1, 0.1), 0.05),
+            ));
+        }
+        // A jump of nothing is no jump, and isn't kept by jfxr.
+        for (_, amount) in &mut self.frequency_jumps {
+            if *amount == 0.0 {
+                *amount = if rng.bool(0.5) { 0.05 } else { -0.05 };
+            }
+        }
+        // Onsets can pass each other.
+        let jumps = std::mem::take(&mut self.frequency_jumps);
+        self.with_jumps(jumps)
+    }
 
+    /// Set the jumps, sorted by onset.
+    pub fn with_jumps(mut self, jumps: impl IntoIterator<Item = (f32, f32)>) -> Self {
+        self.frequency_jumps = jumps.into_iter().collect();
+        self.frequency_jumps.sort_by(|a, b| a.0.total_cmp(&b.0));
         self
     }
 
+    /// Check that the jumps are finite, sorted by onset, and have amounts above -1, so the
+    /// frequency stays positive.
+    pub fn check_jumps(&self) -> Result<(), crate::Error> {
+        let invalid = |reason| crate::Error::Invalid {
+            parameter: "frequency jumps",
+            reason,
+        };
+        if !self
+            .frequency_jumps
+            .iter()
+            .all(|(onset, amount)| onset.is_finite() && amount.is_finite())
+        {
+            return Err(invalid("not finite"));
+        }
+        if self
+            .frequency_jumps
+            .iter()
+            .any(|(_, amount)| *amount <= -1.0)
+        {
+            return Err(invalid("amount not above -1"));
+        }
+        if !self.frequency_jumps.is_sorted_by(|a, b| a.0 <= b.0) {
+            return Err(invalid("not sorted by onset"));
+        }
+        Ok(())
+    }
+
     // The first few t values are 0. Is this a bug with the envelope?
     pub fn to_net(self, len1: f32) -> Net32 {
         let erf = self.repeat_frequency.max(len1);
@@ -216,16 +384,11 @@ impl Pitch {
                 // Delta sweep is quadratic.
                 + t_repeat * t_repeat * self.frequency_delta_sweep;
 
-            // Jump 1.
-            let jump = self.frequency_jump1;
-            if t_repeat > jump.0 {
-                f *= 1.0 + jump.1;
-            }
-
-            // Jump 2.
-            let jump = self.frequency_jump2;
-            if t_repeat > jump.0 {
-                f *= 1.0 + jump.1;
+            // Jumps.
+            for (onset, amount) in &self.frequency_jumps {
+                if t_repeat > *onset {
+                    f *= 1.0 + amount;
+                }
             }
 
             // Vibrato.
@@ -244,6 +407,7 @@ impl Pitch {
 
 flags! {
     #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     pub enum Waveform: u32 {
         #[default]
         Sine,
@@ -267,16 +431,28 @@ impl Waveform {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Tone {
     pub waveform: Waveform,
     pub interpolate_noise: bool,
     pub square_duty: f32,
     pub square_duty_sweep: f32,
+    /// At most [`MAX_HARMONICS`].
     pub harmonics: u32,
     pub harmonics_falloff: f32,
+    /// Use band-limited square, saw and triangle oscillators instead of jfxr's naive (aliased)
+    /// ones.
+    pub band_limited: bool,
 }
 
+/// The most harmonics a tone has, as in jfxr.
+pub const MAX_HARMONICS: u32 = 5;
+
 impl Default for Tone {
     fn default() -> Self {
         Self {
@@ -286,28 +462,37 @@ impl Default for Tone {
             square_duty_sweep: 0.0,
             harmonics: 0,
             harmonics_falloff: 0.5,
+            band_limited: false,
         }
     }
 }
 
 impl fmt::Display for Tone {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = f.alternate();
+        let p = |v, prec| Prec(v, (!all).then_some(prec));
+
         write!(f, "tone: {:?}", self.waveform)?;
         if self.interpolate_noise {
             write!(f, " interp")?;
         }
-        if matches!(self.waveform, Waveform::Square) && self.square_duty != 0.5 {
+        if self.band_limited {
+            write!(f, " band_limited")?;
+        }
+        if all || matches!(self.waveform, Waveform::Square) && self.square_duty != 0.5 {
             write!(
                 f,
-                " duty: {:.2} sweep: {:.2}",
-                self.square_duty, self.square_duty_sweep
+                " duty: {} sweep: {}",
+                p(self.square_duty, 2),
+                p(self.square_duty_sweep, 2)
             )?;
         }
-        if self.harmonics > 0 {
+        if all || self.harmonics > 0 {
             write!(
                 f,
-                " harmonics: {} falloff: {:.1}",
-                self.harmonics, self.harmonics_falloff
+                " harmonics: {} falloff: {}",
+                self.harmonics,
+                p(self.harmonics_falloff, 1)
             )?;
         }
         Ok(())
@@ -325,7 +510,11 @@ impl Tone {
         mutate_f32!(self.square_duty, rng, 0.5, 0.0, 1.0, 0.05);
         mutate_f32!(self.square_duty_sweep, rng, 0.0, -1.0, 1.0, 0.05);
 
-        self.harmonics = clamp(0, 5, self.harmonics as i32 + i32_in(rng, -1, 1)) as u32;
+        self.harmonics = clamp(
+            0,
+            MAX_HARMONICS as i32,
+            self.harmonics as i32 + i32_in(rng, -1, 1),
+        ) as u32;
         mutate_f32!(self.harmonics_falloff, rng, 0.5, 0.0, 1.0, 0.01);
         self
     }
@@ -356,7 +545,9 @@ impl Tone {
 
         let wave = match self.waveform {
             Waveform::Sine => sine() | sink,
+            Waveform::Triangle if self.band_limited => osc::blep_triangle() | sink,
             Waveform::Triangle => osc::triangle() | sink,
+            Waveform::Saw if self.band_limited => osc::blep_saw() | sink,
             Waveform::Saw => osc::saw() | sink,
             Waveform::Square => {
                 // Square duty sweep repeats with frequency repeat cycle.
@@ -367,13 +558,19 @@ impl Tone {
                         self.square_duty + self.square_duty_sweep * r, //t * len1,
                     )
                 }));
-                (pass() | duty) >> osc::square()
+                let square = if self.band_limited {
+                    wrap(osc::blep_square())
+                } else {
+                    wrap(osc::square())
+                };
+                (pass() | duty) >> square
             }
             Waveform::Tangent => osc::tangent() | sink,
             Waveform::Whistle => osc::whistle() | sink,
             Waveform::Breaker => osc::breaker() | sink,
             Waveform::White => osc::white(self.interpolate_noise) | sink,
             Waveform::Pink => osc::white(self.interpolate_noise) >> pinkpass() | sink,
+            #[allow(clippy::precedence)]
             Waveform::Brown => {
                 wrap(osc::white(self.interpolate_noise) >> lowpole_hz(10.0) * dc(13.7)) | sink
             }
@@ -396,7 +593,12 @@ impl From<Waveform> for Tone {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Amplitude {
     pub attack: f32,
     pub sustain: f32,
@@ -408,24 +610,29 @@ pub struct Amplitude {
 
 impl fmt::Display for Amplitude {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = f.alternate();
+        let p = |v| Prec(v, (!all).then_some(2));
+
         write!(f, "amplitude:")?;
-        if self.attack > 0.0 {
-            write!(f, " {:.2} attack", self.attack)?;
+        if all || self.attack > 0.0 {
+            write!(f, " {} attack", p(self.attack))?;
         }
-        if self.sustain > 0.0 {
-            write!(f, " {:.2} sustain", self.sustain)?;
+        if all || self.sustain > 0.0 {
+            write!(f, " {} sustain", p(self.sustain))?;
         }
-        if self.punch > 0.0 {
-            write!(f, " {:.2} punch", self.punch)?;
+        if all || self.punch > 0.0 {
+            write!(f, " {} punch", p(self.punch))?;
         }
-        if self.decay > 0.0 {
-            write!(f, " {:.2} decay", self.decay)?;
+        if all || self.decay > 0.0 {
+            write!(f, " {} decay", p(self.decay))?;
         }
-        if self.tremolo_depth > 0.0 {
+        // The depth is a fraction.
+        if all || self.tremolo_depth > 0.0 {
             write!(
                 f,
-                " tremolo: {:.0}/{:.0}",
-                self.tremolo_depth, self.tremolo_frequency
+                " tremolo: {}/{}",
+                p(self.tremolo_depth),
+                Prec(self.tremolo_frequency, (!all).then_some(0))
             )?;
         }
         Ok(())
@@ -478,8 +685,14 @@ pub fn aspd(amplitude: Amplitude, t: f32) -> f32 {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Filters {
+    /// Milliseconds, as in jfxr.
     pub flanger_offset: f32,
     pub flanger_offset_sweep: f32,
     pub bit_crush: i32,
@@ -489,6 +702,10 @@ pub struct Filters {
     pub high_pass_cutoff: f32,
     pub high_pass_sweep: f32,
     pub compression: f32,
+    /// Scale the whole render so its peak is at full scale.
+    pub normalization: bool,
+    /// Gain after normalization, as a fraction (jfxr has a percentage).
+    pub amplification: f32,
 }
 
 impl Default for Filters {
@@ -503,45 +720,60 @@ impl Default for Filters {
             high_pass_cutoff: 0.0,
             high_pass_sweep: 0.0,
             compression: 1.0,
-            //normalization: true,
-            //amplification: 1.0,
+            normalization: true,
+            amplification: 1.0,
         }
     }
 }
 
 impl fmt::Display for Filters {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.flanger_offset > 0.0 || self.flanger_offset_sweep != 0.0 {
+        let all = f.alternate();
+        let p = |v, prec| Prec(v, (!all).then_some(prec));
+
+        if all || self.flanger_offset > 0.0 || self.flanger_offset_sweep != 0.0 {
             write!(
                 f,
-                " flanger: {:.1}/{:.1}",
-                self.flanger_offset, self.flanger_offset_sweep
+                " flanger: {}/{}",
+                p(self.flanger_offset, 1),
+                p(self.flanger_offset_sweep, 1)
             )?;
         }
-        if self.bit_crush < 16 {
+        if all || self.bit_crush < 16 {
             write!(f, " bit_crush: {}/{}", self.bit_crush, self.bit_crush_sweep)?;
         }
-        if self.low_pass_cutoff < 22_050.0 || self.low_pass_sweep != 0.0 {
+        if all || self.low_pass_cutoff < 22_050.0 || self.low_pass_sweep != 0.0 {
             write!(
                 f,
-                " low_pass: {:.0}/{:.0}",
-                self.low_pass_cutoff, self.low_pass_sweep
+                " low_pass: {}/{}",
+                p(self.low_pass_cutoff, 0),
+                p(self.low_pass_sweep, 0)
             )?;
         }
-        if self.high_pass_cutoff > 0.0 || self.high_pass_sweep != 0.0 {
+        if all || self.high_pass_cutoff > 0.0 || self.high_pass_sweep != 0.0 {
             write!(
                 f,
-                " high_pass: {:.0}/{:.0}",
-                self.high_pass_cutoff, self.high_pass_sweep
+                " high_pass: {}/{}",
+                p(self.high_pass_cutoff, 0),
+                p(self.high_pass_sweep, 0)
             )?;
         }
-        if self.compression != 1.0 {
-            write!(f, " compression: {:.1}", self.compression)?;
+        if all || self.compression != 1.0 {
+            write!(f, " compression: {}", p(self.compression, 1))?;
+        }
+        if all || !self.normalization {
+            write!(f, " normalization: {}", self.normalization)?;
+        }
+        if all || self.amplification != 1.0 {
+            write!(f, " amplification: {}", p(self.amplification, 2))?;
         }
         Ok(())
     }
 }
 
+/// The longest tail of a single filter, in seconds.
+pub const TAIL_LIMIT: f32 = 1.0;
+
 // https://github.com/SamiPerttu/funutd/issues/1
 #[inline]
 fn i32_in(rng: &mut Rnd, min: i32, max: i32) -> i32 {
@@ -568,11 +800,14 @@ impl Filters {
         self
     }
 
-    pub fn to_net(self, len1: f32) -> Net32 {
+    /// Cutoffs are limited to the Nyquist frequency of `sample_rate`.
+    pub fn to_net(self, len1: f32, sample_rate: f64) -> Net32 {
+        let nyquist = (sample_rate / 2.0) as f32;
         let mut f = wrap(pass());
 
-        let delay1 = self.flanger_offset;
-        let sweep = self.flanger_offset_sweep;
+        // In seconds.
+        let delay1 = self.flanger_offset * 0.001;
+        let sweep = self.flanger_offset_sweep * 0.001;
         let delay2 = (delay1 + sweep).max(0.0);
 
         // jfxr does not clamp to 0 and sounds very loud without normalization. It also just sounds
@@ -599,7 +834,7 @@ impl Filters {
             f = (f | lfo(move |t| {
                 clamp(
                     0.0,
-                    DEFAULT_SR as f32 / 2.0,
+                    nyquist,
                     self.low_pass_cutoff + self.low_pass_sweep * t * len1,
                 )
             })) >> lowpole();
@@ -609,7 +844,7 @@ impl Filters {
             f = (f | lfo(move |t| {
                 clamp(
                     0.0,
-                    DEFAULT_SR as f32 / 2.0,
+                    nyquist,
                     self.high_pass_cutoff + self.high_pass_sweep * t * len1,
                 )
             })) >> highpole();
@@ -630,6 +865,184 @@ impl Filters {
 
         f
     }
+
+    /// How long the filters ring on after their input ends, in seconds: the longest flanger delay
+    /// plus the time for each one-pole filter to decay by 60 dB at its final cutoff. Capped at
+    /// [`TAIL_LIMIT`] per filter, since a cutoff near zero never decays.
+    pub fn tail(&self) -> f32 {
+        // One-pole decay: 60 dB is ln(1000) time constants.
+        let decay = |cutoff: f32| {
+            (1000f32.ln() / (std::f32::consts::TAU * cutoff.max(0.0))).min(TAIL_LIMIT)
+        };
+
+        let mut tail = 0.0;
+        if self.flanger_offset > 0.0 || self.flanger_offset + self.flanger_offset_sweep > 0.0 {
+            tail += self
+                .flanger_offset
+                .max(self.flanger_offset + self.flanger_offset_sweep)
+                * 0.001;
+        }
+        if self.low_pass_cutoff < 22_050.0 {
+            tail += decay(self.low_pass_cutoff + self.low_pass_sweep);
+        }
+        if self.high_pass_cutoff > 0.0 {
+            tail += decay(self.high_pass_cutoff + self.high_pass_sweep);
+        }
+        tail
+    }
+
+    /// Normalization and amplification, which need the whole render. Like jfxr, the result is
+    /// clipped to full scale.
+    pub fn apply_gain(&self, wave: &mut Wave32) {
+        let Some(gain) = self.gain(wave.amplitude()) else {
+            return;
+        };
+        for channel in 0..wave.channels() {
+            for i in 0..wave.len() {
+                wave.set(channel, i, gain.apply(wave.at(channel, i)));
+            }
+        }
+    }
+
+    /// The gain for a render with the given peak, if any.
+    pub(crate) fn gain(&self, peak: f32) -> Option<Gain> {
+        if !self.normalization && self.amplification == 1.0 {
+            return None;
+        }
+        Some(Gain {
+            // As `Wave32::normalize`.
+            scale: (self.normalization && peak != 0.0 && peak != 1.0).then(|| 1.0 / peak),
+            amplification: self.amplification,
+        })
+    }
+}
+
+/// Normalization and amplification of each sample, once the peak is known.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Gain {
+    scale: Option<f32>,
+    amplification: f32,
+}
+
+impl Gain {
+    pub(crate) fn apply(&self, x: f32) -> f32 {
+        let x = self.scale.map_or(x, |scale| x * scale);
+        (x * self.amplification).clamp(-1.0, 1.0)
+    }
+}
+
+/// Placement in the stereo field. Panning is equal power, like fundsp's `pan`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Stereo {
+    /// -1 (left) to 1 (right).
+    pub pan: f32,
+    /// Change in pan over the length of the sound.
+    pub pan_sweep: f32,
+    /// Auto-pan swing either side, as a fraction of the full field.
+    pub auto_pan_depth: f32,
+    pub auto_pan_frequency: f32,
+    /// 0 is mono, 1 is widest. The sides are the sound plus and minus a short delayed copy.
+    pub width: f32,
+}
+
+const AUTO_PAN_FREQUENCY_DEFAULT: f32 = 1.0;
+
+/// The delay of the copy that widens the sound, in seconds.
+const WIDTH_DELAY: f32 = 0.012;
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Self {
+            pan: 0.0,
+            pan_sweep: 0.0,
+            auto_pan_depth: 0.0,
+            auto_pan_frequency: AUTO_PAN_FREQUENCY_DEFAULT,
+            width: 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Stereo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let all = f.alternate();
+        let p = |v, prec| Prec(v, (!all).then_some(prec));
+
+        write!(f, "stereo:")?;
+        if all || self.pan != 0.0 {
+            write!(f, " pan: {}", p(self.pan, 2))?;
+        }
+        if all || self.pan_sweep != 0.0 {
+            write!(f, " sweep: {}", p(self.pan_sweep, 2))?;
+        }
+        if all || self.auto_pan_depth > 0.0 {
+            write!(
+                f,
+                " auto_pan: {}/{}",
+                p(self.auto_pan_depth, 2),
+                p(self.auto_pan_frequency, 1)
+            )?;
+        }
+        if all || self.width > 0.0 {
+            write!(f, " width: {}", p(self.width, 2))?;
+        }
+        Ok(())
+    }
+}
+
+impl Stereo {
+    pub fn mutate(mut self, rng: &mut Rnd) -> Self {
+        mutate_f32!(self.pan, rng, 0.0, -1.0, 1.0, 0.05);
+        mutate_f32!(self.pan_sweep, rng, 0.0, -2.0, 2.0, 0.05);
+        mutate_f32!(self.auto_pan_depth, rng, 0.0, 0.0, 1.0, 0.01);
+        #[rustfmt::skip]
+        mutate_f32!(self.auto_pan_frequency, rng, AUTO_PAN_FREQUENCY_DEFAULT, 0.0, 20.0, 0.1);
+        mutate_f32!(self.width, rng, 0.0, 0.0, 1.0, 0.01);
+        self
+    }
+
+    /// The pan at `t` seconds, with `len1` the inverse of the sound length.
+    pub fn pan_at(&self, t: f32, len1: f32) -> f32 {
+        let mut pan = self.pan + self.pan_sweep * t * len1;
+        if self.auto_pan_depth > 0.0 {
+            pan += self.auto_pan_depth * sin_hz(self.auto_pan_frequency, t);
+        }
+        clamp(-1.0, 1.0, pan)
+    }
+
+    /// The widening delay.
+    pub fn tail(&self) -> f32 {
+        if self.width > 0.0 {
+            WIDTH_DELAY
+        } else {
+            0.0
+        }
+    }
+
+    /// Mono in, stereo out.
+    pub fn to_net(self, len1: f32) -> Net32 {
+        let w = self.width;
+        let sides = if w > 0.0 {
+            wrap(pass() ^ delay(WIDTH_DELAY))
+                >> map(move |f: &Frame<f32, U2>| {
+                    // Scaled so a sound and its copy in phase don't clip.
+                    let (mid, side) = (f[0], w * f[1]);
+                    Frame::<f32, U2>::from([(mid + side) / (1.0 + w), (mid - side) / (1.0 + w)])
+                })
+        } else {
+            wrap(pass() ^ pass())
+        };
+
+        (sides | lfo(move |t| self.pan_at(t, len1)))
+            >> map(|f: &Frame<f32, U3>| {
+                let angle = (f[2] + 1.0) * std::f32::consts::FRAC_PI_4;
+                Frame::<f32, U2>::from([f[0] * angle.cos(), f[1] * angle.sin()])
+            })
+    }
 }
 
 #[cfg(test)]
@@ -637,7 +1050,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn square_duty_sweep_repeat() {
+    fn square_duty_sweep_repeat() -> Result<(), crate::Error> {
         let asyn = Asyn {
             pitch: Pitch {
                 frequency: 220.0,
@@ -657,13 +1070,11 @@ mod tests {
             ..Default::default()
         };
 
-        asyn.to_wav()
-            .save_wav16("square_duty_sweep_repeat.wav")
-            .unwrap();
+        asyn.save_wav("square_duty_sweep_repeat.wav", crate::SampleFormat::I16)
     }
 
     #[test]
-    fn bit_crush() {
+    fn bit_crush() -> Result<(), crate::Error> {
         let asyn = Asyn {
             pitch: Pitch {
                 frequency: 110.0,
@@ -686,6 +1097,202 @@ mod tests {
             ..Default::default()
         };
 
-        asyn.to_wav().save_wav16("4bit.wav").unwrap();
+        asyn.save_wav("4bit.wav", crate::SampleFormat::I16)
+    }
+
+    #[test]
+    fn sample_rates() {
+        for waveform in [Waveform::Sine, Waveform::Square, Waveform::Triangle] {
+            let asyn = Asyn {
+                pitch: Pitch {
+                    frequency: 440.0,
+                    ..Default::default()
+                },
+                tone: Tone::from(waveform),
+                amplitude: Amplitude {
+                    sustain: 0.5,
+                    ..Default::default()
+                },
+                filters: Some(Filters {
+                    // Above Nyquist at 22050.
+                    low_pass_cutoff: 15_000.0,
+                    normalization: false,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+
+            for sample_rate in [22_050.0, 44_100.0, 48_000.0, 96_000.0] {
+                let wave = asyn.clone().to_wav_at(sample_rate);
+                assert_eq!(wave.sample_rate(), sample_rate);
+                assert!((wave.duration() - 0.5).abs() < 1e-4);
+
+                let rising = (1..wave.len())
+                    .filter(|&i| wave.at(0, i - 1) < 0.0 && wave.at(0, i) >= 0.0)
+                    .count();
+                assert!(
+                    rising.abs_diff(220) <= 1,
+                    "{waveform:?} {sample_rate}: {rising}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn normalization_amplification() {
+        let peak = |wave: &Wave32| (0..wave.len()).fold(0.0f32, |p, i| p.max(wave.at(0, i).abs()));
+        let asyn = Asyn {
+            amplitude: Amplitude {
+                sustain: 0.1,
+                ..Default::default()
+            },
+            filters: Some(Filters {
+                compression: 2.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let mut f = asyn.filters.clone().unwrap();
+        assert!((peak(&asyn.clone().to_wav()) - 1.0).abs() < 1e-6);
+
+        f.amplification = 0.5;
+        let half = Asyn {
+            filters: Some(f.clone()),
+            ..asyn.clone()
+        };
+        assert!((peak(&half.to_wav()) - 0.5).abs() < 1e-6);
+
+        f.normalization = false;
+        f.amplification = 4.0;
+        let clipped = Asyn {
+            filters: Some(f),
+            ..asyn
+        };
+        assert_eq!(peak(&clipped.to_wav()), 1.0);
+    }
+
+    #[test]
+    fn stereo() {
+        let rms = |wave: &Wave32, channel, range: std::ops::Range<usize>| {
+            let n = range.len() as f32;
+            (range.map(|i| wave.at(channel, i).powi(2)).sum::<f32>() / n).sqrt()
+        };
+        let mono = Asyn {
+            tone: Tone::from(Waveform::Triangle),
+            amplitude: Amplitude {
+                sustain: 0.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let wave = mono.clone().to_wav();
+        assert_eq!(wave.channels(), 1);
+        let level = rms(&wave, 0, 0..wave.len());
+
+        let left = Asyn {
+            stereo: Some(Stereo {
+                pan: -1.0,
+                ..Default::default()
+            }),
+            ..mono.clone()
+        };
+        let wave = left.to_wav();
+        assert_eq!(wave.channels(), 2);
+        assert!((rms(&wave, 0, 0..wave.len()) - level).abs() < 1e-3);
+        assert!(rms(&wave, 1, 0..wave.len()) < 1e-3);
+
+        // Left to right.
+        let sweep = Asyn {
+            stereo: Some(Stereo {
+                pan: -1.0,
+                pan_sweep: 2.0,
+                ..Default::default()
+            }),
+            ..mono.clone()
+        };
+        let wave = sweep.to_wav();
+        let (start, end) = (0..wave.len() / 4, wave.len() * 3 / 4..wave.len());
+        assert!(rms(&wave, 0, start.clone()) > 4.0 * rms(&wave, 1, start));
+        assert!(rms(&wave, 1, end.clone()) > 4.0 * rms(&wave, 0, end));
+
+        // Centered, but the sides differ.
+        let wide = Asyn {
+            stereo: Some(Stereo {
+                width: 1.0,
+                ..Default::default()
+            }),
+            ..mono
+        };
+        let wave = wide.to_wav();
+        assert!((0..wave.len()).any(|i| (wave.at(0, i) - wave.at(1, i)).abs() > 0.1));
+    }
+
+    #[test]
+    fn tail_and_trim() {
+        let asyn = Asyn {
+            tone: Tone::from(Waveform::Sine),
+            amplitude: Amplitude {
+                sustain: 0.2,
+                decay: 0.1,
+                ..Default::default()
+            },
+            filters: Some(Filters {
+                flanger_offset: 20.0,
+                flanger_offset_sweep: -10.0,
+                low_pass_cutoff: 500.0,
+                low_pass_sweep: -100.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let tail = 0.02 + 1000f32.ln() / (std::f32::consts::TAU * 400.0);
+        assert!((asyn.tail() - tail).abs() < 1e-6);
+        assert!((asyn.len() - 0.3 - tail).abs() < 1e-6);
+
+        let wave = asyn.clone().to_wav();
+        assert!((wave.duration() as f32 - asyn.len()).abs() < 1e-4);
+        // The flanger's delayed copy is still sounding after the envelope ends.
+        let end = (0.3 * DEFAULT_SR) as usize;
+        assert!((end..wave.len()).any(|i| wave.at(0, i).abs() > 0.001));
+
+        let trimmed = asyn.render(&RenderOptions {
+            trim: Some(0.01),
+            ..Default::default()
+        });
+        assert!(trimmed.len() < wave.len());
+        assert!(wave.at(0, trimmed.len() - 1).abs() >= 0.01);
+        assert!((trimmed.len()..wave.len()).all(|i| wave.at(0, i).abs() < 0.01));
+
+        assert_eq!(
+            Filters {
+                low_pass_cutoff: 0.0,
+                ..Default::default()
+            }
+            .tail(),
+            TAIL_LIMIT
+        );
+    }
+
+    #[test]
+    fn mutate_jumps() {
+        let rng = &mut Rnd::from_u64(24);
+        let mut pitch = Pitch::default();
+        for _ in 0..50 {
+            pitch = pitch.mutate(rng);
+            assert!(pitch
+                .frequency_jumps
+                .iter()
+                .all(|(_, amount)| *amount != 0.0));
+            pitch.check_jumps().unwrap();
+        }
+        // More than sfxr and jfxr have.
+        assert!(pitch.frequency_jumps.len() > 2);
+
+        // No frequency at all.
+        assert!(Pitch::default()
+            .with_jumps([(0.5, -1.0)])
+            .check_jumps()
+            .is_err());
     }
 }